@@ -0,0 +1,109 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+
+use crate::auth::delete_user_data;
+use crate::error::ApiError;
+use crate::extract::{Json, Path};
+use crate::gamedata::refresh_game_data;
+use crate::roles::Admin;
+use crate::state::AppState;
+
+/// POST /admin/gamedata/sync - pulls game data now instead of waiting for the daily sync.
+/// It runs in the background, watch the logs for when it's done
+pub async fn sync_game_data(State(state): State<AppState>, Admin(admin): Admin) -> StatusCode {
+    println!("game data sync started by user {}", admin.userId);
+    tokio::spawn(async move {
+        refresh_game_data(&state).await;
+    });
+
+    StatusCode::ACCEPTED
+}
+
+#[derive(Serialize, FromRow)]
+pub struct UserSummary {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub createdAt: String,
+    pub isAdmin: bool,
+    /// The default ally code
+    pub allyCode: String,
+    pub linkedAccounts: i64
+}
+
+/// GET /admin/users - every user, oldest first
+pub async fn list_users(State(state): State<AppState>, _admin: Admin) -> Result<Json<Vec<UserSummary>>, ApiError> {
+    let mut users = sqlx::query_as::<_, UserSummary>(r#"
+        SELECT user.id, user.username, user.email, user.createdAt, user.isAdmin, user.allyCode,
+            (SELECT COUNT(*) FROM linkedAccount WHERE linkedAccount.userId = user.id) AS linkedAccounts
+        FROM user
+        ORDER BY user.id
+    "#)
+    .fetch_all(&state.pool)
+    .await?;
+
+    // Admins from ADMIN_USERNAMES don't have the flag stored
+    for user in &mut users {
+        user.isAdmin |= state.config.admin_usernames.contains(&user.username);
+    }
+
+    Ok(Json(users))
+}
+
+#[derive(Deserialize)]
+pub struct SetAdminPayload {
+    isAdmin: bool
+}
+
+/// PUT /admin/users/{userId}/admin - makes a user an admin or takes it away
+pub async fn set_admin(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(userId): Path<i64>,
+    Json(payload): Json<SetAdminPayload>
+) -> Result<StatusCode, ApiError> {
+    if userId == admin.userId && !payload.isAdmin {
+        return Err(ApiError::BadRequest("Admins can't remove their own admin role".to_string()));
+    }
+
+    let result = sqlx::query(r#"UPDATE user SET isAdmin = ? WHERE id = ?"#)
+        .bind(payload.isAdmin)
+        .bind(userId)
+        .execute(&state.pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound(format!("User {} not found", userId)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// DELETE /admin/users/{userId} - deletes a user the same way DELETE /user does
+pub async fn remove_user(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+    Path(userId): Path<i64>
+) -> Result<StatusCode, ApiError> {
+    if userId == admin.userId {
+        return Err(ApiError::BadRequest("Use DELETE /user to delete your own account".to_string()));
+    }
+
+    let mut tx = state.pool.begin().await?;
+    let exists: bool = sqlx::query_scalar(r#"SELECT EXISTS(SELECT 1 FROM user WHERE id = ?)"#)
+        .bind(userId)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !exists {
+        return Err(ApiError::NotFound(format!("User {} not found", userId)));
+    }
+
+    let deleted = delete_user_data(&mut tx, userId).await?;
+    tx.commit().await?;
+    println!("admin {} deleted user {} and {} ally codes", admin.userId, userId, deleted);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+//curl -X POST http://localhost:7474/admin/gamedata/sync -H "Authorization: Bearer <admin token>"
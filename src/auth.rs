@@ -0,0 +1,635 @@
+use axum::{
+    extract::{FromRef, FromRequestParts, State}, http::{StatusCode, header, request::Parts}
+};
+use serde::{Serialize, Deserialize};
+use sqlx::Row;
+use chrono::{Utc, Duration};
+use argon2::{
+    Argon2, password_hash::{
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng
+    }
+};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, encode, decode};
+use rand::{Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::account::refreshAccount;
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct SignInPayload {
+    username: String,
+    password: String
+}
+
+/// A token identifies the user, not an ally code, so accounts can be linked and unlinked without signing in again
+#[derive(Deserialize, Serialize)]
+pub struct Claims {
+    /// user.id of the signed in user
+    pub sub: String,
+    /// The sign-in this token came from, shared with its refresh tokens
+    pub sid: String,
+    /// Unique per token so a single token can be revoked
+    pub jti: String,
+    pub iat: usize,
+    pub exp: usize
+}
+
+/// Access tokens are short lived, clients use the refresh token to get a new one
+const ACCESS_TOKEN_MINUTES: i64 = 60;
+const REFRESH_TOKEN_DAYS: i64 = 30;
+
+fn random_token(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Refresh and reset tokens are long random strings so a fast hash is enough, and it lets us look them up by hash
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Serialize)]
+pub struct TokenPair {
+    pub token: String,
+    pub refreshToken: String,
+    /// Seconds until `token` expires
+    pub expiresIn: i64
+}
+
+/// Signs a new access token and stores a new refresh token for the session
+async fn issue_tokens(
+    state: &AppState,
+    conn: &mut SqliteConnection,
+    userId: i64,
+    sessionId: &str
+) -> Result<TokenPair, ApiError> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: userId.to_string(),
+        sid: sessionId.to_string(),
+        jti: random_token(24),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::minutes(ACCESS_TOKEN_MINUTES)).timestamp() as usize
+    };
+
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(state.config.jwt_secret.as_ref()))
+        .map_err(|e| ApiError::Internal(format!("Failed to sign token: {}", e)))?;
+
+    let refreshToken = random_token(48);
+    sqlx::query(r#"
+        INSERT INTO refreshToken (tokenHash, userId, sessionId, createdAt, expiresAt)
+        VALUES (?, ?, ?, ?, ?)
+    "#)
+    .bind(hash_token(&refreshToken))
+    .bind(userId)
+    .bind(sessionId)
+    .bind(now.to_rfc3339())
+    .bind((now + Duration::days(REFRESH_TOKEN_DAYS)).timestamp())
+    .execute(conn)
+    .await?;
+
+    Ok(TokenPair {
+        token,
+        refreshToken,
+        expiresIn: ACCESS_TOKEN_MINUTES * 60
+    })
+}
+
+/// Checks the password and starts a new session, returning an access token and a refresh token
+pub async fn signIn(State(state): State<AppState>, Json(payload): Json<SignInPayload>) -> Result<Json<TokenPair>, ApiError>{
+    println!("we signing in");
+    let bad_login = || ApiError::Unauthorized("Invalid username or password".to_string());
+    let account_info = sqlx::query(
+        r#"
+            SELECT * FROM user WHERE username = ?
+        "#
+    )
+    .bind(&payload.username)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(bad_login)?;
+
+    let userId: i64 = account_info.try_get("id")?;
+    let password_hash: String = account_info.try_get("password")?;
+
+    let parsed_hash = PasswordHash::new(&password_hash)
+    .map_err(|e| ApiError::Internal(format!("Stored password hash is invalid: {}", e)))?;
+
+    Argon2::default()
+    .verify_password(payload.password.as_bytes(), &parsed_hash)
+    .map_err(|_| bad_login())?;
+
+    let mut conn = state.pool.acquire().await?;
+    let tokens = issue_tokens(&state, &mut conn, userId, &random_token(24)).await?;
+
+    Ok(Json(tokens))
+}
+
+#[derive(Deserialize)]
+pub struct RefreshPayload {
+    refreshToken: String
+}
+
+/// Trades a refresh token for a new access token and refresh token, the old refresh token stops working.
+/// A refresh token that was already used means it was copied, so the whole session is ended
+pub async fn refresh(State(state): State<AppState>, Json(payload): Json<RefreshPayload>) -> Result<Json<TokenPair>, ApiError> {
+    let invalid = || ApiError::Unauthorized("Invalid refresh token".to_string());
+    let tokenHash = hash_token(&payload.refreshToken);
+
+    let (userId, sessionId, expiresAt, revoked): (i64, String, i64, bool) = sqlx::query_as(
+        r#"SELECT userId, sessionId, expiresAt, revoked FROM refreshToken WHERE tokenHash = ?"#
+    )
+    .bind(&tokenHash)
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(invalid)?;
+
+    if revoked {
+        println!("refresh token reused, ending session {}", sessionId);
+        revoke_session(&state.pool, &sessionId).await?;
+        return Err(invalid());
+    }
+    if expiresAt < Utc::now().timestamp() {
+        return Err(ApiError::TokenExpired);
+    }
+
+    // The old token is revoked and the new one stored together, so the session is never without a live token
+    let mut tx = state.pool.begin().await?;
+
+    // Only one request can rotate a token, a second one racing it sees no rows changed
+    let rotated = sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE tokenHash = ? AND revoked = 0"#)
+        .bind(&tokenHash)
+        .execute(&mut *tx)
+        .await?;
+    if rotated.rows_affected() == 0 {
+        return Err(invalid());
+    }
+
+    let tokens = issue_tokens(&state, &mut tx, userId, &sessionId).await?;
+    tx.commit().await?;
+
+    Ok(Json(tokens))
+}
+
+async fn revoke_session(pool: &SqlitePool, sessionId: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE sessionId = ?"#)
+        .bind(sessionId)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Deserialize, Default)]
+pub struct SignOutPayload {
+    #[serde(default)]
+    everywhere: bool
+}
+
+/// Ends the caller's session, the access token and its refresh tokens stop working right away.
+/// With `everywhere` every session the user has is ended, on any device
+pub async fn signOut(
+    State(state): State<AppState>,
+    AuthBearer(user): AuthBearer,
+    payload: Option<Json<SignOutPayload>>
+) -> Result<StatusCode, ApiError> {
+    let claims = &user.claims;
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let now = Utc::now().timestamp();
+    let mut tx = state.pool.begin().await?;
+
+    sqlx::query(r#"INSERT OR IGNORE INTO revokedToken (jti, expiresAt) VALUES (?, ?)"#)
+        .bind(&claims.jti)
+        .bind(claims.exp as i64)
+        .execute(&mut *tx)
+        .await?;
+
+    if payload.everywhere {
+        // Access tokens from other sessions stop working with their session, see AuthBearer
+        sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE userId = ?"#)
+            .bind(user.userId)
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE sessionId = ?"#)
+            .bind(&claims.sid)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Nothing needs to remember a token once it would have expired anyway
+    sqlx::query(r#"DELETE FROM revokedToken WHERE expiresAt < ?"#)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(r#"DELETE FROM refreshToken WHERE expiresAt < ?"#)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn hash_password(password: &str) -> Result<String, ApiError> {
+    // Generate a random salt
+    let salt = SaltString::generate(&mut OsRng);
+
+    // Hash the password
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| ApiError::Internal(format!("Failed to hash password: {}", e)))?
+        .to_string();
+
+    Ok(password_hash)
+}
+
+/// Checks `password` against the user's stored hash
+async fn check_password(state: &AppState, userId: i64, password: &str) -> Result<(), ApiError> {
+    let password_hash: String = sqlx::query_scalar(r#"SELECT password FROM user WHERE id = ?"#)
+        .bind(userId)
+        .fetch_one(&state.pool)
+        .await?;
+
+    let parsed_hash = PasswordHash::new(&password_hash)
+        .map_err(|e| ApiError::Internal(format!("Stored password hash is invalid: {}", e)))?;
+
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .map_err(|_| ApiError::Unauthorized("Password is incorrect".to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct SignUpPayload {
+    username: String,
+    password: String,
+    allyCode: String,
+    email: String
+}
+
+
+pub async fn signUp(State(state): State<AppState>, Json(payload): Json<SignUpPayload>) -> Result<StatusCode, ApiError> {
+    if payload.email.parse::<lettre::Address>().is_err() {
+        return Err(ApiError::BadRequest(format!("Invalid email address {}", payload.email)));
+    }
+
+    // Checked before comlink is called so a taken username doesn't cost a player fetch.
+    // The insert below still catches two sign ups racing for the same name
+    let taken: bool = sqlx::query_scalar(r#"SELECT EXISTS(SELECT 1 FROM user WHERE username = ?)"#)
+        .bind(&payload.username)
+        .fetch_one(&state.pool)
+        .await?;
+    if taken {
+        return Err(ApiError::Conflict(format!("Username {} is already taken", payload.username)));
+    }
+
+    let password_hash = hash_password(&payload.password)?;
+
+    let _ = refreshAccount(&state, payload.allyCode.clone()).await?;
+    println!("setting plater to db");
+
+    // Insert user into database, the ally code they sign up with is linked and becomes their default
+    let mut tx = state.pool.begin().await?;
+    let result = sqlx::query::<sqlx::Sqlite>(
+        r#"
+        INSERT INTO user (
+            username, password, createdAt, allyCode, email
+        ) VALUES (?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&payload.username)
+    .bind(&password_hash)
+    .bind(Utc::now().to_rfc3339())
+    .bind(&payload.allyCode)
+    .bind(&payload.email)
+    .execute(&mut *tx)
+    .await;
+
+    let userId = match result.map_err(ApiError::from) {
+        Ok(result) => result.last_insert_rowid(),
+        Err(ApiError::Conflict(_)) => return Err(ApiError::Conflict(format!("Username {} is already taken", payload.username))),
+        Err(e) => return Err(e)
+    };
+
+    link_account(&mut tx, userId, &payload.allyCode).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Adds an ally code to the user's linked accounts, unverified until /verify passes for it
+pub(crate) async fn link_account(conn: &mut SqliteConnection, userId: i64, allyCode: &str) -> Result<(), ApiError> {
+    sqlx::query(r#"INSERT INTO linkedAccount (userId, allyCode, linkedAt) VALUES (?, ?, ?)"#)
+        .bind(userId)
+        .bind(allyCode)
+        .bind(Utc::now().to_rfc3339())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Requests pick which linked ally code they act as with this header, without it the user's default is used
+pub const ALLY_CODE_HEADER: &str = "x-ally-code";
+
+/// The signed in user and the linked ally code this request is for
+pub struct AuthUser {
+    pub userId: i64,
+    pub allyCode: String,
+    /// Whether the user has proven they own `allyCode`
+    pub verified: bool,
+    pub claims: Claims
+}
+
+pub struct AuthBearer(pub AuthUser);
+
+impl<S> FromRequestParts<S> for AuthBearer
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        // Try to extract the Authorization header
+        let auth_header = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| ApiError::Unauthorized("Missing Authorization header".to_string()))?;
+
+        // Check for the correct "Bearer " prefix
+        if !auth_header.starts_with("Bearer ") {
+            return Err(ApiError::Unauthorized("Invalid token format".into()));
+        }
+
+        // Extract the token part (after "Bearer ")
+        let token = auth_header.trim_start_matches("Bearer ").trim();
+
+        // Decode and validate the JWT
+        let state = AppState::from_ref(state);
+
+        let decoded = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(state.config.jwt_secret.as_ref()),
+            &Validation::default(),
+        )?;
+        let claims = decoded.claims;
+        let userId: i64 = claims.sub.parse()
+            .map_err(|_| ApiError::Unauthorized("Invalid token".to_string()))?;
+
+        // The signature is fine, make sure the token hasn't been signed out since it was issued.
+        // A session is signed in while it has a refresh token that isn't revoked
+        let (sessionLive, revoked): (bool, bool) = sqlx::query_as(r#"
+            SELECT EXISTS(SELECT 1 FROM refreshToken WHERE sessionId = ? AND userId = ? AND revoked = 0),
+                EXISTS(SELECT 1 FROM revokedToken WHERE jti = ?)
+        "#)
+        .bind(&claims.sid)
+        .bind(userId)
+        .bind(&claims.jti)
+        .fetch_one(&state.pool)
+        .await?;
+
+        if !sessionLive || revoked {
+            return Err(ApiError::Unauthorized("Token has been revoked".to_string()));
+        }
+
+        // If we reach here, the token is valid! Now work out which ally code the request is for
+        let requested = parts
+            .headers
+            .get(ALLY_CODE_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.trim().to_string());
+
+        let linked: Option<(String, bool)> = sqlx::query_as(r#"
+            SELECT linkedAccount.allyCode, linkedAccount.verified
+            FROM user
+                JOIN linkedAccount ON linkedAccount.userId = user.id
+            WHERE user.id = ? AND linkedAccount.allyCode = COALESCE(?, user.allyCode)
+        "#)
+        .bind(userId)
+        .bind(&requested)
+        .fetch_optional(&state.pool)
+        .await?;
+
+        let (allyCode, verified) = linked.ok_or_else(|| match &requested {
+            Some(allyCode) => ApiError::Forbidden(format!("Ally code {} is not linked to this account", allyCode)),
+            None => ApiError::Forbidden("No default ally code, pick one with the X-Ally-Code header".to_string()),
+        })?;
+
+        Ok(AuthBearer(AuthUser { userId, allyCode, verified, claims }))
+    }
+}
+
+/// Like AuthBearer but also requires the user to have proven they own the ally code, see verify.rs.
+/// Anything that changes stored data or reads private per ally code data (plans, history, mods) takes this
+pub struct VerifiedUser(pub AuthUser);
+
+impl<S> FromRequestParts<S> for VerifiedUser
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthBearer(user) = AuthBearer::from_request_parts(parts, state).await?;
+
+        if !user.verified {
+            return Err(ApiError::Forbidden(format!("Verify ally code {} first, see /verify/challenge", user.allyCode)));
+        }
+
+        Ok(VerifiedUser(user))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordPayload {
+    currentPassword: String,
+    newPassword: String
+}
+
+/// POST /password - changes the password, every other session is signed out
+pub async fn change_password(
+    State(state): State<AppState>,
+    AuthBearer(user): AuthBearer,
+    Json(payload): Json<ChangePasswordPayload>
+) -> Result<StatusCode, ApiError> {
+    check_password(&state, user.userId, &payload.currentPassword).await?;
+    let password_hash = hash_password(&payload.newPassword)?;
+
+    let mut tx = state.pool.begin().await?;
+    sqlx::query(r#"UPDATE user SET password = ? WHERE id = ?"#)
+        .bind(&password_hash)
+        .bind(user.userId)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE userId = ? AND sessionId != ?"#)
+        .bind(user.userId)
+        .bind(&user.claims.sid)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// How long an emailed reset token works for
+const RESET_TOKEN_MINUTES: i64 = 60;
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordPayload {
+    username: String
+}
+
+/// POST /password/forgot - emails a reset token to the address the user signed up with.
+/// Always answers 202 so it can't be used to find out which usernames exist
+pub async fn forgot_password(State(state): State<AppState>, Json(payload): Json<ForgotPasswordPayload>) -> Result<StatusCode, ApiError> {
+    let user: Option<(i64, String)> = sqlx::query_as(r#"SELECT id, email FROM user WHERE username = ?"#)
+        .bind(&payload.username)
+        .fetch_optional(&state.pool)
+        .await?;
+
+    let Some((userId, email)) = user else {
+        println!("password reset asked for unknown user {}", payload.username);
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    let token = random_token(32);
+    let now = Utc::now();
+    sqlx::query(r#"
+        INSERT INTO passwordReset (tokenHash, userId, createdAt, expiresAt)
+        VALUES (?, ?, ?, ?)
+    "#)
+    .bind(hash_token(&token))
+    .bind(userId)
+    .bind(now.to_rfc3339())
+    .bind((now + Duration::minutes(RESET_TOKEN_MINUTES)).timestamp())
+    .execute(&state.pool)
+    .await?;
+
+    let body = format!(
+        "Someone asked to reset the password for {}. If it was you, use this reset token within {} minutes:\n\n{}\n\nIf it wasn't you, you can ignore this email.",
+        payload.username, RESET_TOKEN_MINUTES, token
+    );
+    // A failed send is logged rather than returned, the answer has to look the same either way
+    if let Err(e) = state.mailer.send(&email, "Reset your SWGOH Utils password", &body).await {
+        eprintln!("Failed to send password reset for user {}: {}", userId, e);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordPayload {
+    token: String,
+    newPassword: String
+}
+
+/// POST /password/reset - sets a new password with an emailed reset token, signing out every session.
+/// Each token works once
+pub async fn reset_password(State(state): State<AppState>, Json(payload): Json<ResetPasswordPayload>) -> Result<StatusCode, ApiError> {
+    let password_hash = hash_password(&payload.newPassword)?;
+    let mut tx = state.pool.begin().await?;
+
+    // Marking the token used and reading it in one statement means two resets racing can't both use it
+    let userId: i64 = sqlx::query_scalar(r#"
+        UPDATE passwordReset SET used = 1
+        WHERE tokenHash = ? AND used = 0 AND expiresAt >= ?
+        RETURNING userId
+    "#)
+    .bind(hash_token(&payload.token))
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::BadRequest("Invalid or expired reset token".to_string()))?;
+
+    sqlx::query(r#"UPDATE user SET password = ? WHERE id = ?"#)
+        .bind(&password_hash)
+        .bind(userId)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(r#"UPDATE refreshToken SET revoked = 1 WHERE userId = ?"#)
+        .bind(userId)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct DeleteUserPayload {
+    password: String
+}
+
+/// DELETE /user - deletes the user and signs them out everywhere. Ally codes they verified are deleted too,
+/// with their plans and roster
+pub async fn delete_user(
+    State(state): State<AppState>,
+    AuthBearer(user): AuthBearer,
+    Json(payload): Json<DeleteUserPayload>
+) -> Result<StatusCode, ApiError> {
+    check_password(&state, user.userId, &payload.password).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let deleted = delete_user_data(&mut tx, user.userId).await?;
+    tx.commit().await?;
+    println!("deleted user {} and {} ally codes", user.userId, deleted);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes the user, their sessions and links, plus the plans and roster of every ally code they verified.
+/// Only the verified owner's data goes, an unverified link is just a link. Returns how many ally codes were removed
+pub(crate) async fn delete_user_data(conn: &mut SqliteConnection, userId: i64) -> Result<usize, ApiError> {
+    let allyCodes: Vec<String> = sqlx::query_scalar(r#"SELECT allyCode FROM linkedAccount WHERE userId = ? AND verified = 1"#)
+        .bind(userId)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    for statement in [
+        r#"DELETE FROM verificationChallenge WHERE userId = ?"#,
+        r#"DELETE FROM linkedAccount WHERE userId = ?"#,
+        r#"DELETE FROM refreshToken WHERE userId = ?"#,
+        r#"DELETE FROM passwordReset WHERE userId = ?"#,
+        r#"DELETE FROM user WHERE id = ?"#,
+    ] {
+        sqlx::query(statement).bind(userId).execute(&mut *conn).await?;
+    }
+
+    for allyCode in &allyCodes {
+        for statement in [
+            r#"DELETE FROM charPlan WHERE planId IN (SELECT id FROM plan WHERE allyCode = ?)"#,
+            r#"DELETE FROM plan WHERE allyCode = ?"#,
+            r#"DELETE FROM modStat WHERE modId IN (SELECT id FROM mod WHERE allyCode = ?)"#,
+            r#"DELETE FROM mod WHERE allyCode = ?"#,
+            r#"DELETE FROM rosterUnit WHERE allyCode = ?"#,
+            r#"DELETE FROM rosterSkill WHERE allyCode = ?"#,
+            r#"DELETE FROM rosterEquipment WHERE allyCode = ?"#,
+            r#"DELETE FROM rosterPurchasedAbility WHERE allyCode = ?"#,
+            r#"DELETE FROM inventory WHERE allyCode = ?"#,
+            r#"DELETE FROM snapshotUnit WHERE snapshotId IN (SELECT id FROM rosterSnapshot WHERE allyCode = ?)"#,
+            r#"DELETE FROM rosterSnapshot WHERE allyCode = ?"#,
+        ] {
+            sqlx::query(statement).bind(allyCode).execute(&mut *conn).await?;
+        }
+    }
+
+    Ok(allyCodes.len())
+}
+
+//curl -X POST http://localhost:7474/password/forgot -H "Content-Type: application/json" -d '{"username": "me"}'
+//curl -X POST http://localhost:7474/password/reset -H "Content-Type: application/json" -d '{"token": "<from the email>", "newPassword": "hunter3"}'
@@ -1,12 +1,12 @@
 #![allow(non_snake_case)]
 
 use serde::{Serialize, Deserialize};
-use axum::{
-    extract::{Json}, http::StatusCode
-};
+use axum::extract::State;
 
 use sqlx::{SqlitePool};
 
+use crate::error::ApiError;
+use crate::extract::Json;
 use crate::types::GameData;
 
 #[derive(Deserialize, Serialize)]
@@ -14,18 +14,15 @@ pub struct CharPayload {
     charId: Option<String>
 }
 
-pub async fn characters(Json(payload): Json<CharPayload>) -> Result<Json<Vec<Character>>, (StatusCode, String)> {
-    let pool = SqlitePool::connect("sqlite:////data/mydb.sqlite").await.unwrap();
+pub async fn characters(State(pool): State<SqlitePool>, Json(payload): Json<CharPayload>) -> Result<Json<Vec<Character>>, ApiError> {
     match payload.charId.as_deref() {
         None | Some("") => {
-            return Ok(Json(getAllChar(&pool).await))
+            return Ok(Json(getAllChar(&pool).await?))
             //Err((StatusCode::BAD_REQUEST, "charId required for now".to_string()))
         }
         Some(charId) => {
-            let char_data = getSingleChar(charId.to_string()).await;
-            let mut returnList:Vec<Character> = Vec::new();
-            returnList.push(char_data);
-            Ok(Json(returnList))
+            let char_data = getSingleChar(&pool, charId.to_string()).await?;
+            Ok(Json(vec![char_data]))
         }
     }
 }
@@ -33,14 +30,12 @@ pub async fn characters(Json(payload): Json<CharPayload>) -> Result<Json<Vec<Cha
 /// Retrieves all characters from the database with their associated data
 /// Returns a Vec of Character structs, each containing unit info, categories, 
 /// crew members, skills, and equipment tiers
-
-async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
+async fn getAllChar(pool: &SqlitePool) -> Result<Vec<Character>, sqlx::Error> {
     println!("getting all characters");
     // Step 1: Fetch ALL base unit information in one query
     let all_units: Vec<UnitRow> = sqlx::query_as("SELECT * FROM unit")
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Step 2: Fetch ALL related data in bulk queries (not per-unit)
     // This dramatically reduces the number of database round-trips
@@ -50,40 +45,35 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
         "SELECT * FROM unit_has_trait"
     )
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Get all skills at once
     let all_skills: Vec<SkillRow> = sqlx::query_as(
         "SELECT * FROM skill"
     )
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Get all crew members at once
     let all_crew: Vec<CrewRow> = sqlx::query_as(
         "SELECT * FROM crew"
     )
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Get all tier information at once
     let all_tiers: Vec<TierRow> = sqlx::query_as(
         "SELECT * FROM unitTier ORDER BY baseId, tier"
     )
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Get all equipment at once
     let all_equipment: Vec<EquipmentRow> = sqlx::query_as(
         "SELECT * FROM equipment"
     )
         .fetch_all(pool)
-        .await
-        .unwrap();
+        .await?;
 
     // Step 3: Build lookup maps for O(1) access instead of linear searches
     // This organizes data by baseId for fast retrieval
@@ -94,7 +84,7 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     for cat in all_categories {
         categories_map
             .entry(cat.baseId.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(cat.category_name);
     }
 
@@ -103,7 +93,7 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     for skill in all_skills {
         skills_map
             .entry(skill.baseId.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(skill.skillId);
     }
 
@@ -112,7 +102,7 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     for crew in all_crew {
         crew_map
             .entry(crew.baseId.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(crew.unitId);
     }
 
@@ -121,7 +111,7 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     for tier in all_tiers {
         tiers_map
             .entry(tier.baseId.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(tier);
     }
 
@@ -130,7 +120,7 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     for equip in all_equipment {
         equipment_map
             .entry((equip.baseId.clone(), equip.tier))
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(equip.equipmentId);
     }
 
@@ -191,27 +181,27 @@ async fn getAllChar(pool: &SqlitePool) -> Vec<Character> {
     }
 
     // Return all constructed characters
-    characters
+    Ok(characters)
 }
 
-async fn getSingleChar(baseId: String) -> Character {
-    let pool = SqlitePool::connect("sqlite:////data/mydb.sqlite").await.unwrap();
+async fn getSingleChar(pool: &SqlitePool, baseId: String) -> Result<Character, ApiError> {
     let unitInfo:UnitRow = sqlx::query_as("SELECT * FROM unit WHERE baseId = ?")
-        .bind(&baseId).fetch_one(&pool).await.unwrap();
+        .bind(&baseId).fetch_optional(pool).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Character {} not found", baseId)))?;
 
     let categories:Vec<CategoryRow> = sqlx::query_as(
         "SELECT * FROM unit_has_trait WHERE baseId = ?"
     )
-        .bind(&baseId).fetch_all(&pool).await.unwrap();
+        .bind(&baseId).fetch_all(pool).await?;
 
     let skills:Vec<SkillRow> = sqlx::query_as("SELECT * FROM skill WHERE baseId = ?")
-        .bind(&baseId).fetch_all(&pool).await.unwrap();
+        .bind(&baseId).fetch_all(pool).await?;
 
     let crew:Vec<CrewRow> = sqlx::query_as("Select * FROM crew WHERE baseId = ?")
-        .bind(&baseId).fetch_all(&pool).await.unwrap();
+        .bind(&baseId).fetch_all(pool).await?;
 
     let unitTier:Vec<TierRow> = sqlx::query_as("SELECT * FROM unitTier WHERE baseId = ?")
-        .bind(&baseId).fetch_all(&pool).await.unwrap();
+        .bind(&baseId).fetch_all(pool).await?;
 
     let category_names:Vec<String>  = categories.into_iter().map(|c| c.category_name).collect();
     let crew_ids:Vec<String> = crew.into_iter().map(|c| c.unitId).collect();
@@ -225,7 +215,7 @@ for tier in unitTier {
     )
     .bind(tier.tier)
     .bind(&tier.baseId)
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await;
 
     let equipment_rows = match equipment_rows_result {
@@ -251,7 +241,9 @@ for tier in unitTier {
 
 
 
-    let character = Character {
+    
+
+    Ok(Character {
         baseId: unitInfo.baseId,
         categoryId: category_names,
         crew: crew_ids,
@@ -260,22 +252,27 @@ for tier in unitTier {
         skillReference: skill_ids,
         thumbnainName: unitInfo.thumbnailName,
         unitTier: tiers
-    };
-
-    return character
+    })
 }
 
 // async fn getAllChar() {
 //     return "HI"
 // }
 
-pub async fn setCharactersToDB(gamedata: &GameData) {
-    let pool = SqlitePool::connect("sqlite:////data/mydb.sqlite").await.unwrap();
+pub async fn setCharactersToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+
+    // Units stored before combatType was kept need it too, so ships are marked on every sync
+    for unit in gamedata.units.iter().filter(|unit| unit.combatType != 1) {
+        sqlx::query(r#"UPDATE unit SET combatType = ? WHERE baseId = ?"#)
+            .bind(unit.combatType)
+            .bind(&unit.baseId)
+            .execute(pool)
+            .await?;
+    }
 
     let existing_ids: Vec<(String,)> = sqlx::query_as("SELECT baseId FROM unit")
-        .fetch_all(&pool)
-        .await
-        .unwrap();
+        .fetch_all(pool)
+        .await?;
     let existing_set: std::collections::HashSet<String> = 
         existing_ids.into_iter().map(|(id,)| id).collect();
     
@@ -286,7 +283,7 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
     
     if new_units.is_empty() {
         println!("No new characters to add");
-        return;
+        return Ok(());
     }
 
 
@@ -295,16 +292,16 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
         sqlx::query(
             r#"
             INSERT OR IGNORE INTO unit (
-            baseId, iconPath, thumbnailName, relicDefinition
-            ) VALUES (?, ?, ?, ?);
+            baseId, iconPath, thumbnailName, relicDefinition, combatType
+            ) VALUES (?, ?, ?, ?, ?);
             "#    
         ).bind(&unit.baseId)
         .bind(&unit.iconPath)
         .bind(&unit.thumbnailName)
-        .bind(&unit.relicDefinition.as_ref().map(|r| &r.texture))
-        .execute(&pool)
-        .await
-        .unwrap();
+        .bind(unit.relicDefinition.as_ref().map(|r| &r.texture))
+        .bind(unit.combatType)
+        .execute(pool)
+        .await?;
         
         for category in &unit.categoryId {
             sqlx::query(
@@ -312,10 +309,9 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                 INSERT OR IGNORE INTO category (category_name)
                 VALUES (?);
                 "#
-            ).bind(&category)
-            .execute(&pool)
-            .await
-            .unwrap();
+            ).bind(category)
+            .execute(pool)
+            .await?;
 
             sqlx::query(
                 r#"
@@ -323,10 +319,9 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                 VALUES (?, ?);
                 "#
             ).bind(&unit.baseId)
-            .bind(&category)
-            .execute(&pool)
-            .await
-            .unwrap();
+            .bind(category)
+            .execute(pool)
+            .await?;
         }
 
         for crew in &unit.crew {
@@ -337,9 +332,8 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                 "#
             ).bind(&crew.unitId)
             .bind(&unit.baseId)
-            .execute(&pool)
-            .await
-            .unwrap();
+            .execute(pool)
+            .await?;
         }
 
         for skill in &unit.skillReference {
@@ -350,9 +344,8 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                 "#
             ).bind(&skill.skillId)
             .bind(&unit.baseId)
-            .execute(&pool)
-            .await
-            .unwrap();
+            .execute(pool)
+            .await?;
         }
 
         for unitTier in &unit.unitTier {
@@ -363,10 +356,9 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                 "#
             )
             .bind(&unit.baseId)
-            .bind(&unitTier.tier)
-            .execute(&pool)
-            .await
-            .unwrap();
+            .bind(unitTier.tier)
+            .execute(pool)
+            .await?;
 
             for equipment in &unitTier.equipmentSet {
                 sqlx::query(
@@ -375,17 +367,36 @@ pub async fn setCharactersToDB(gamedata: &GameData) {
                     VALUES (?, ?, ?);
                     "#
                 )
-                .bind(&equipment)
-                .bind(&unitTier.tier)
+                .bind(equipment)
+                .bind(unitTier.tier)
                 .bind(&unit.baseId)
-                .execute(&pool)
-                .await
-                .unwrap();
+                .execute(pool)
+                .await?;
             }
         }
 
     }
     println!("all units added");
+    Ok(())
+}
+
+/// Marks the tier each skill gets its zeta and omicron at. Runs after setCharactersToDB so the skills exist
+pub async fn setSkillsToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    println!("setting {} skills", gamedata.skill.len());
+    let mut tx = pool.begin().await?;
+
+    for skill in &gamedata.skill {
+        sqlx::query(r#"UPDATE skill SET zetaTier = ?, omicronTier = ?, omicronMode = ? WHERE skillId = ?"#)
+            .bind(skill.first_tier(|tier| tier.isZetaTier).map(|tier| tier as i64))
+            .bind(skill.first_tier(|tier| tier.isOmicronTier).map(|tier| tier as i64))
+            .bind(skill.omicronMode)
+            .bind(&skill.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
 }
 
 #[derive(Deserialize, Serialize)]
@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::error::ApiError;
+use crate::types::{GameData, GameMetadata, Guild, GuildResponse, Player};
+
+/// How a player is looked up, comlink accepts either one
+#[derive(Clone, Debug)]
+pub enum PlayerLookup {
+    AllyCode(String),
+    PlayerId(String),
+}
+
+impl PlayerLookup {
+    fn payload(&self) -> Value {
+        match self {
+            PlayerLookup::AllyCode(allyCode) => json!({ "allyCode": allyCode }),
+            PlayerLookup::PlayerId(playerId) => json!({ "playerId": playerId }),
+        }
+    }
+}
+
+impl std::fmt::Display for PlayerLookup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerLookup::AllyCode(allyCode) => write!(f, "ally code {}", allyCode),
+            PlayerLookup::PlayerId(playerId) => write!(f, "player id {}", playerId),
+        }
+    }
+}
+
+/// Everything the API asks comlink for
+#[async_trait]
+pub trait ComlinkClient: Send + Sync {
+    async fn metadata(&self) -> Result<GameMetadata, ApiError>;
+    async fn data(&self, version: &str, segment: u32) -> Result<GameData, ApiError>;
+    async fn player(&self, lookup: &PlayerLookup) -> Result<Player, ApiError>;
+    async fn guild(&self, guildId: &str) -> Result<Guild, ApiError>;
+}
+
+/// Talks to a running comlink container
+pub struct HttpComlink {
+    client: Client,
+    url: String,
+}
+
+impl HttpComlink {
+    pub fn new(client: Client, url: String) -> HttpComlink {
+        HttpComlink { client, url }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<reqwest::Response, ApiError> {
+        Ok(self.client.post(format!("{}{}", self.url, path)).json(&body).send().await?)
+    }
+}
+
+#[async_trait]
+impl ComlinkClient for HttpComlink {
+    async fn metadata(&self) -> Result<GameMetadata, ApiError> {
+        let response = self.client.post(format!("{}/metadata", self.url)).send().await?;
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn data(&self, version: &str, segment: u32) -> Result<GameData, ApiError> {
+        let body = json!({
+            "payload": {
+                "version": version,
+                "includePveUnits": false,
+                "requestSegment": segment,
+            },
+            "enums": false
+        });
+        Ok(self.post("/data", body).await?.error_for_status()?.json().await?)
+    }
+
+    async fn player(&self, lookup: &PlayerLookup) -> Result<Player, ApiError> {
+        let body = json!({
+            "payload": lookup.payload(),
+            "enums": false
+        });
+        let response = self.post("/player", body).await?;
+
+        // comlink answers an unknown ally code or player id with a 4xx
+        if response.status().is_client_error() {
+            return Err(ApiError::NotFound(format!("Player with {} not found", lookup)));
+        }
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    async fn guild(&self, guildId: &str) -> Result<Guild, ApiError> {
+        let body = json!({
+            "payload": {
+                "guildId": guildId,
+                "includeRecentGuildActivityInfo": false
+            },
+            "enums": false
+        });
+        let response = self.post("/guild", body).await?;
+
+        if response.status().is_client_error() {
+            return Err(ApiError::NotFound(format!("Guild {} not found", guildId)));
+        }
+
+        let guild: GuildResponse = response.error_for_status()?.json().await?;
+        Ok(guild.guild)
+    }
+}
+
+/// Serves comlink responses saved as JSON files instead of calling comlink, for running without the container.
+/// The directory holds `metadata.json`, `data-{segment}.json`, `player-{allyCode}.json` and `guild-{guildId}.json`,
+/// each one the body comlink would have sent back
+pub struct FixtureComlink {
+    dir: PathBuf,
+}
+
+impl FixtureComlink {
+    pub fn new(dir: impl Into<PathBuf>) -> FixtureComlink {
+        FixtureComlink { dir: dir.into() }
+    }
+
+    /// Reads a fixture, None if the file doesn't exist
+    async fn read<T: DeserializeOwned>(&self, filename: &str) -> Result<Option<T>, ApiError> {
+        let path = self.dir.join(filename);
+        let contents = match tokio::fs::read(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ApiError::Upstream(format!("Failed to read fixture {}: {}", path.display(), e))),
+        };
+
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| ApiError::Upstream(format!("Fixture {} is invalid: {}", path.display(), e)))
+    }
+
+    async fn require<T: DeserializeOwned>(&self, filename: &str) -> Result<T, ApiError> {
+        self.read(filename)
+            .await?
+            .ok_or_else(|| ApiError::Upstream(format!("Missing fixture {}", filename)))
+    }
+
+    /// Players are saved by ally code, finding one by player id means checking each of them
+    async fn find_player_id(&self, playerId: &str) -> Result<Option<Player>, ApiError> {
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .map_err(|e| ApiError::Upstream(format!("Failed to read fixtures: {}", e)))?;
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let filename = entry.file_name().to_string_lossy().to_string();
+            if !filename.starts_with("player-") {
+                continue;
+            }
+            if let Some(player) = self.read::<Player>(&filename).await?
+                && player.playerId == playerId {
+                return Ok(Some(player));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl ComlinkClient for FixtureComlink {
+    async fn metadata(&self) -> Result<GameMetadata, ApiError> {
+        self.require("metadata.json").await
+    }
+
+    async fn data(&self, _version: &str, segment: u32) -> Result<GameData, ApiError> {
+        self.require(&format!("data-{}.json", segment)).await
+    }
+
+    async fn player(&self, lookup: &PlayerLookup) -> Result<Player, ApiError> {
+        let player = match lookup {
+            PlayerLookup::AllyCode(allyCode) => self.read(&format!("player-{}.json", allyCode)).await?,
+            PlayerLookup::PlayerId(playerId) => self.find_player_id(playerId).await?,
+        };
+
+        player.ok_or_else(|| ApiError::NotFound(format!("Player with {} not found", lookup)))
+    }
+
+    async fn guild(&self, guildId: &str) -> Result<Guild, ApiError> {
+        let guild: Option<GuildResponse> = self.read(&format!("guild-{}.json", guildId)).await?;
+
+        guild
+            .map(|guild| guild.guild)
+            .ok_or_else(|| ApiError::NotFound(format!("Guild {} not found", guildId)))
+    }
+}
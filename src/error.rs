@@ -0,0 +1,174 @@
+use std::fmt;
+
+use axum::{
+    Json,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response}
+};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+
+/// One failed check on a request field, `index` is the position in the list that was sent
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub index: usize,
+    pub baseId: String,
+    pub field: String,
+    pub message: String
+}
+
+/// Every handler returns this on failure. The response body is always
+/// `{"error": {"code": "...", "message": "..."}}`, validation failures add a `details` list
+/// and rate limits a `retryAfter` in seconds
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    TokenExpired,
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Validation(Vec<ValidationError>),
+    /// Slow down, the request can be tried again after this many seconds. Sent with a Retry-After header
+    TooManyRequests { message: String, retryAfter: u64 },
+    /// comlink couldn't be reached or sent back something we couldn't read
+    Upstream(String),
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) | ApiError::TokenExpired => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine-readable name for the error, clients should match on this rather than the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::TokenExpired => "token_expired",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::TooManyRequests { .. } => "rate_limited",
+            ApiError::Upstream(_) => "comlink_unavailable",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Upstream(message)
+            | ApiError::Internal(message)
+            | ApiError::TooManyRequests { message, .. } => write!(f, "{}", message),
+            ApiError::TokenExpired => write!(f, "Token has expired"),
+            ApiError::Validation(errors) => {
+                let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+                write!(f, "{}", messages.join("; "))
+            }
+            // The query and driver details go to the log, not the client
+            ApiError::Database(_) => write!(f, "Database error"),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        match &self {
+            ApiError::Database(e) => eprintln!("Database error: {}", e),
+            ApiError::Upstream(message) | ApiError::Internal(message) => eprintln!("{}: {}", self.code(), message),
+            _ => {}
+        }
+
+        let mut error = json!({
+            "code": self.code(),
+            "message": self.to_string()
+        });
+        if let ApiError::Validation(errors) = &self {
+            error["details"] = json!(errors);
+        }
+        if let ApiError::TooManyRequests { retryAfter, .. } = &self {
+            error["retryAfter"] = json!(retryAfter);
+            return (status, [(header::RETRY_AFTER, retryAfter.to_string())], Json(json!({ "error": error }))).into_response();
+        }
+
+        (status, Json(json!({ "error": error }))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> ApiError {
+        match &e {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Not found".to_string()),
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => ApiError::Conflict("Already exists".to_string()),
+            _ => ApiError::Database(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> ApiError {
+        if e.is_decode() {
+            ApiError::Upstream(format!("Unexpected response from comlink: {}", e.without_url()))
+        } else {
+            ApiError::Upstream(format!("comlink request failed: {}", e.without_url()))
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(e: jsonwebtoken::errors::Error) -> ApiError {
+        match e.kind() {
+            jsonwebtoken::errors::ErrorKind::ExpiredSignature => ApiError::TokenExpired,
+            _ => ApiError::Unauthorized("Invalid token".to_string()),
+        }
+    }
+}
+
+/// Failures from axum's own extractors, see extract.rs. Anything the client sent wrong is a bad request
+fn from_rejection(status: StatusCode, message: String) -> ApiError {
+    match status.is_server_error() {
+        true => ApiError::Internal(message),
+        false => ApiError::BadRequest(message),
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> ApiError {
+        from_rejection(e.status(), e.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(e: PathRejection) -> ApiError {
+        from_rejection(e.status(), e.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> ApiError {
+        from_rejection(e.status(), e.body_text())
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashSet, path::Path};
+
+use reqwest::Client;
+use sqlx::SqlitePool;
+use tokio::{fs::{self, File}, io::AsyncWriteExt};
+
+use crate::characters::{setCharactersToDB, setSkillsToDB};
+use crate::comlink::ComlinkClient;
+use crate::error::ApiError;
+use crate::gear::setEquipmentToDB;
+use crate::relic::setRelicsToDB;
+use crate::stars::{setMaterialsToDB, setStarPromotionToDB};
+use crate::state::AppState;
+use crate::stats::setUnitStatsToDB;
+use crate::types::{GameData, GameMetadata};
+
+/// Pulls the latest game data from comlink and stores it, failures are logged and left for the next run
+pub async fn refresh_game_data(state: &AppState) {
+    println!("checking for game data updates");
+
+    match get_game_data(state).await {
+        Ok(new_game_data) => store_game_data(&new_game_data, &state.pool).await,
+        Err(e) => {
+            eprintln!("Failed to update game data : {}", e)
+        }
+    }
+}
+
+/// Writes game data to every table built from it. Each part is stored on its own so one failing doesn't stop the rest
+pub async fn store_game_data(gamedata: &GameData, pool: &SqlitePool) {
+    if let Err(e) = setCharactersToDB(gamedata, pool).await {
+        eprintln!("Failed to update characters : {}", e)
+    }
+    if let Err(e) = setSkillsToDB(gamedata, pool).await {
+        eprintln!("Failed to update skills : {}", e)
+    }
+    if let Err(e) = setUnitStatsToDB(gamedata, pool).await {
+        eprintln!("Failed to update unit stats : {}", e)
+    }
+    if let Err(e) = setEquipmentToDB(gamedata, pool).await {
+        eprintln!("Failed to update equipment : {}", e)
+    }
+    if let Err(e) = setMaterialsToDB(gamedata, pool).await {
+        eprintln!("Failed to update materials : {}", e)
+    }
+    if let Err(e) = setRelicsToDB(gamedata, pool).await {
+        eprintln!("Failed to update relic tiers : {}", e)
+    }
+    if let Err(e) = setStarPromotionToDB(gamedata, pool).await {
+        eprintln!("Failed to update star promotion costs : {}", e)
+    }
+    println!("game data updated");
+}
+
+pub async fn get_game_data(state: &AppState) -> Result<GameData, ApiError> {
+    let (metadata, gamedata) = fetch_game_data(state.comlink.as_ref()).await?;
+    save(&gamedata).await;
+
+    get_assets(state, &gamedata, &metadata.assetVersion).await;
+    let gamedata = add_images_gamedata(gamedata);
+    println!("Setup complete");
+    Ok(gamedata)
+}
+
+/// The latest game data from comlink with the unit and equipment segments merged, before any assets are fetched
+pub async fn fetch_game_data(comlink: &dyn ComlinkClient) -> Result<(GameMetadata, GameData), ApiError> {
+    println!("Getting game metadata...");
+    let metadata = comlink.metadata().await?;
+    println!("Asset Version: {}", metadata.assetVersion);
+    println!("Getting game data... (Version: {})", metadata.latestGamedataVersion);
+    let mut gamedata = comlink.data(&metadata.latestGamedataVersion, UNIT_SEGMENT).await?;
+
+    println!("Getting equipment data...");
+    let equipment_data = comlink.data(&metadata.latestGamedataVersion, EQUIPMENT_SEGMENT).await?;
+    gamedata.equipment = equipment_data.equipment;
+    gamedata.recipe = equipment_data.recipe;
+    gamedata.material = equipment_data.material;
+    gamedata.relicTierDefinition = equipment_data.relicTierDefinition;
+    gamedata.skill = equipment_data.skill;
+    gamedata.table = equipment_data.table;
+
+    Ok((metadata, splice_game_data(gamedata)))
+}
+
+/// comlink splits /data into segments, units live in 3 and equipment, recipes, materials, relic tiers, skills and tables in 1
+const UNIT_SEGMENT: u32 = 3;
+const EQUIPMENT_SEGMENT: u32 = 1;
+
+async fn save(data: &GameData) {
+    let mut save_file = File::create("data.json").await.unwrap();
+    save_file.write_all(serde_json::to_string_pretty(data).unwrap().as_bytes()).await.unwrap();
+}
+
+
+fn splice_game_data(mut gamedata: GameData) -> GameData {
+    let filter_keywords = ["_GLE", "SPEEDERBIKE", "MARQUEE", "EVENT"];
+    let mut seen_base_ids:HashSet<String> = HashSet::new();
+
+    gamedata.units.retain(|unit| {
+        // Check if baseId contains any unwanted substrings
+        let contains_blocked = filter_keywords.iter().any(|keyword| unit.baseId.contains(keyword));
+        
+        // If it's blocked, we remove it
+        if contains_blocked {
+            return false;
+        }
+
+        // If it's a duplicate, we remove it
+        if !seen_base_ids.insert(unit.baseId.clone()) {
+            return false;
+        }
+
+        true // Otherwise, keep it
+    });
+
+    gamedata
+}
+
+
+async fn get_assets(state: &AppState, data:&GameData, asset_version:&u32) {
+    println!("{}", asset_version);
+    let mut asset_list:Vec<String> = vec![];
+
+    if !Path::new("assets").exists() {
+        fs::create_dir_all("assets").await.expect("Failed to create directory");
+    }
+
+    let asset_files: HashSet<String> = std::fs::read_dir("assets")
+    .unwrap()
+    .filter_map(|entry| {
+        entry.ok().and_then(|e| {
+            e.file_name()
+                .into_string()
+                .ok()
+        })
+    })
+    .collect();
+
+    for unit in &data.units {
+        let check_name = format!("{}.png", unit.thumbnailName);
+        if !asset_files.contains(&check_name) {
+            let asset_name = unit.thumbnailName.trim_start_matches("tex.").to_string();
+            asset_list.push(asset_name);
+        }
+    }
+
+    for (cur_num, asset) in asset_list.iter().enumerate() {
+        let number = &asset_list.len();
+        println!("Downloading asset: {} ({}/{})", asset, cur_num, number);
+        let url = format!("{}/Asset/single?assetName={}&version={}&forceReDownload=false", state.config.asset_extractor_url, &asset, &asset_version);
+        // A missing thumbnail shouldn't stop the game data from being stored
+        if let Err(e) = download_asset(&state.client, url, asset).await {
+            eprintln!("Failed to download asset {} : {}", asset, e);
+        }
+    }
+}
+
+async fn download_asset(client: &Client, url: String, filename: &str) -> Result<(), Box<dyn std::error::Error>>{
+    let response = client.get(url).send().await?;
+
+    let directory = "assets";
+    let download_path = Path::new(directory);
+    let full_filename = format!("tex.{}.png", filename);
+    let full_path = download_path.join(full_filename);
+
+    if !Path::new(directory).exists() {
+        fs::create_dir_all(directory).await?;
+    }
+
+    let mut file = File::create(full_path).await?;
+    let bytes = response.bytes().await?;
+    
+    file.write_all(&bytes).await?;
+
+    Ok(())
+}
+
+pub fn add_images_gamedata(mut gamedata:GameData) -> GameData {
+    for unit in &mut gamedata.units {
+        let filepath = format!("assets/{}.png", unit.thumbnailName);
+        unit.iconPath = Some(filepath);
+    }
+
+    gamedata
+}
@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::State;
+use serde::{Serialize, Deserialize};
+use sqlx::{SqlitePool, prelude::FromRow};
+
+use crate::auth::VerifiedUser;
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::plan::{check_plan_owner, goal_errors, load_ships, unknown_unit_errors};
+use crate::types::{CREDITS_ID, GameData};
+
+#[derive(Deserialize, Serialize)]
+pub struct GearPayload {
+    planId: Option<i64>,
+    characters: Option<Vec<GearGoal>>
+}
+
+#[derive(Deserialize, Serialize, FromRow)]
+pub struct GearGoal {
+    baseId: String,
+    goalGear: i64
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GearCount {
+    pub equipmentId: String,
+    pub quantity: u32
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CharGearNeeded {
+    pub baseId: String,
+    pub currentGear: i64,
+    pub goalGear: i64,
+    pub gear: Vec<GearCount>
+}
+
+#[derive(Deserialize, Serialize, FromRow)]
+pub struct DropLocation {
+    pub campaignId: String,
+    pub campaignMapId: String,
+    pub campaignNodeDifficulty: i64,
+    pub campaignNodeId: String,
+    pub campaignMissionId: String
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SalvageCount {
+    pub equipmentId: String,
+    pub quantity: u32,
+    pub dropLocations: Vec<DropLocation>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GearNeeded {
+    pub characters: Vec<CharGearNeeded>,
+    pub total: Vec<GearCount>,
+    pub salvage: Vec<SalvageCount>,
+    pub credits: u64
+}
+
+fn to_counts(counts: BTreeMap<String, u32>) -> Vec<GearCount> {
+    counts.into_iter()
+        .map(|(equipmentId, quantity)| GearCount { equipmentId, quantity })
+        .collect()
+}
+
+/// Lists the gear pieces the caller still needs to take characters from their current gear tier
+/// to a goal, either for a saved plan (`planId`) or an ad-hoc list of `characters`. Gear already
+/// slotted at the current tier isn't counted
+pub async fn gear_needed(
+    State(pool): State<SqlitePool>,
+    VerifiedUser(user): VerifiedUser,
+    Json(payload): Json<GearPayload>
+) -> Result<Json<GearNeeded>, ApiError> {
+    let allyCode = &user.allyCode;
+
+    let goals: Vec<GearGoal> = match (payload.planId, payload.characters) {
+        (Some(planId), _) => {
+            check_plan_owner(&pool, planId, allyCode).await?;
+            sqlx::query_as(r#"SELECT baseId, goalGear FROM charPlan WHERE planId = ? ORDER BY priority, id"#)
+                .bind(planId)
+                .fetch_all(&pool)
+                .await?
+        }
+        (None, Some(characters)) => characters,
+        (None, None) => return Err(ApiError::BadRequest("planId or characters required".to_string()))
+    };
+
+    let ships = load_ships(&pool).await?;
+    let mut errors = match payload.planId {
+        Some(_) => Vec::new(),
+        None => {
+            let baseIds: Vec<&str> = goals.iter().map(|goal| goal.baseId.as_str()).collect();
+            unknown_unit_errors(&pool, &baseIds).await?
+        }
+    };
+    errors.extend(goals.iter()
+        .enumerate()
+        .flat_map(|(index, goal)| goal_errors(index, &goal.baseId, &ships, None, Some(goal.goalGear), None)));
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let mut characters: Vec<CharGearNeeded> = Vec::new();
+    let mut total: BTreeMap<String, u32> = BTreeMap::new();
+
+    for goal in goals {
+        // Locked characters start at gear 1 once they're unlocked
+        let currentGear: i64 = sqlx::query_scalar(r#"SELECT currentTier FROM rosterUnit WHERE allyCode = ? AND definitionId = ?"#)
+            .bind(allyCode)
+            .bind(&goal.baseId)
+            .fetch_optional(&pool)
+            .await?
+            .unwrap_or(1);
+
+        // A tier's equipmentSet is what gets slotted at that tier to promote to the next one,
+        // so G12 -> G13 needs tier 12's set and nothing from tier 13
+        let equipment: Vec<String> = sqlx::query_scalar(r#"
+            SELECT equipmentId FROM equipment
+            WHERE baseId = ? AND tier >= ? AND tier < ?
+        "#)
+        .bind(&goal.baseId)
+        .bind(currentGear)
+        .bind(goal.goalGear)
+        .fetch_all(&pool)
+        .await?;
+
+        let mut gear: BTreeMap<String, u32> = BTreeMap::new();
+        for equipmentId in equipment {
+            *gear.entry(equipmentId).or_default() += 1;
+        }
+
+        // Pieces already slotted at the current tier are done
+        if currentGear < goal.goalGear {
+            let equipped: Vec<String> = sqlx::query_scalar(r#"SELECT equipmentId FROM rosterEquipment WHERE allyCode = ? AND baseId = ?"#)
+                .bind(allyCode)
+                .bind(&goal.baseId)
+                .fetch_all(&pool)
+                .await?;
+            for equipmentId in equipped {
+                if let Some(quantity) = gear.get_mut(&equipmentId) {
+                    *quantity = quantity.saturating_sub(1);
+                }
+            }
+            gear.retain(|_, quantity| *quantity > 0);
+        }
+
+        for (equipmentId, quantity) in &gear {
+            *total.entry(equipmentId.clone()).or_default() += quantity;
+        }
+
+        characters.push(CharGearNeeded {
+            baseId: goal.baseId,
+            currentGear,
+            goalGear: goal.goalGear,
+            gear: to_counts(gear)
+        });
+    }
+
+    let recipes = Recipes::load(&pool).await?;
+    let mut leaves: BTreeMap<String, u32> = BTreeMap::new();
+    let mut credits: u64 = 0;
+    for (equipmentId, quantity) in &total {
+        recipes.expand(equipmentId, *quantity, &mut leaves, &mut credits, 0);
+    }
+
+    let mut salvage: Vec<SalvageCount> = Vec::new();
+    for (equipmentId, quantity) in leaves {
+        let dropLocations: Vec<DropLocation> = sqlx::query_as(r#"
+            SELECT campaignId, campaignMapId, campaignNodeDifficulty, campaignNodeId, campaignMissionId
+            FROM dropLocation WHERE itemId = ?
+        "#)
+        .bind(&equipmentId)
+        .fetch_all(&pool)
+        .await?;
+
+        salvage.push(SalvageCount { equipmentId, quantity, dropLocations });
+    }
+
+    Ok(Json(GearNeeded {
+        characters,
+        total: to_counts(total),
+        salvage,
+        credits
+    }))
+}
+
+/// Recipes never nest this deep, this only guards against a cycle in bad game data
+const MAX_RECIPE_DEPTH: u32 = 10;
+
+/// Equipment recipes loaded from the database, used to break finished gear down into salvage
+pub(crate) struct Recipes {
+    recipeIds: HashMap<String, String>,
+    ingredients: HashMap<String, Vec<(String, u32)>>
+}
+
+impl Recipes {
+    pub(crate) async fn load(pool: &SqlitePool) -> Result<Recipes, sqlx::Error> {
+        let recipeIds: Vec<(String, String)> = sqlx::query_as(
+            r#"SELECT equipmentId, recipeId FROM equipmentDefinition WHERE recipeId != ''"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let rows: Vec<(String, String, i64)> = sqlx::query_as(
+            r#"SELECT recipeId, ingredientId, quantity FROM recipeIngredient"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut ingredients: HashMap<String, Vec<(String, u32)>> = HashMap::new();
+        for (recipeId, ingredientId, quantity) in rows {
+            ingredients
+                .entry(recipeId)
+                .or_default()
+                .push((ingredientId, quantity as u32));
+        }
+
+        Ok(Recipes {
+            recipeIds: recipeIds.into_iter().collect(),
+            ingredients
+        })
+    }
+
+    /// Recursively adds `quantity` of `itemId` to `leaves` as the pieces that can't be crafted any further.
+    /// Crafting credits along the way are added to `credits`
+    pub(crate) fn expand(&self, itemId: &str, quantity: u32, leaves: &mut BTreeMap<String, u32>, credits: &mut u64, depth: u32) {
+        let parts = self.recipeIds
+            .get(itemId)
+            .and_then(|recipeId| self.ingredients.get(recipeId))
+            .filter(|parts| parts.iter().any(|(id, _)| id != CREDITS_ID));
+
+        match parts {
+            Some(parts) if depth < MAX_RECIPE_DEPTH => {
+                for (ingredientId, amount) in parts {
+                    if ingredientId == CREDITS_ID {
+                        *credits += *amount as u64 * quantity as u64;
+                    } else {
+                        self.expand(ingredientId, amount * quantity, leaves, credits, depth + 1);
+                    }
+                }
+            }
+            _ => {
+                *leaves.entry(itemId.to_string()).or_default() += quantity;
+            }
+        }
+    }
+}
+
+/// Replaces the stored equipment definitions, recipes and gear drop locations with the latest game data
+pub async fn setEquipmentToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    println!("setting {} equipment and {} recipes", gamedata.equipment.len(), gamedata.recipe.len());
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(r#"DELETE FROM dropLocation WHERE itemId IN (SELECT equipmentId FROM equipmentDefinition)"#)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(r#"DELETE FROM equipmentDefinition"#).execute(&mut *tx).await?;
+    sqlx::query(r#"DELETE FROM recipeIngredient"#).execute(&mut *tx).await?;
+
+    for equipment in &gamedata.equipment {
+        sqlx::query(r#"
+            INSERT OR REPLACE INTO equipmentDefinition (equipmentId, nameKey, tier, mark, recipeId)
+            VALUES (?, ?, ?, ?, ?)
+        "#)
+        .bind(&equipment.id)
+        .bind(&equipment.nameKey)
+        .bind(equipment.tier)
+        .bind(&equipment.mark)
+        .bind(&equipment.recipeId)
+        .execute(&mut *tx)
+        .await?;
+
+        for mission in &equipment.lookupMission {
+            let location = &mission.missionIdentifier;
+            sqlx::query(r#"
+                INSERT OR IGNORE INTO dropLocation
+                    (itemId, campaignId, campaignMapId, campaignNodeDifficulty, campaignNodeId, campaignMissionId)
+                VALUES (?, ?, ?, ?, ?, ?)
+            "#)
+            .bind(&equipment.id)
+            .bind(&location.campaignId)
+            .bind(&location.campaignMapId)
+            .bind(location.campaignNodeDifficulty)
+            .bind(&location.campaignNodeId)
+            .bind(&location.campaignMissionId)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    for recipe in &gamedata.recipe {
+        for ingredient in &recipe.ingredients {
+            sqlx::query(r#"
+                INSERT OR REPLACE INTO recipeIngredient (recipeId, ingredientId, quantity)
+                VALUES (?, ?, ?)
+            "#)
+            .bind(&recipe.id)
+            .bind(&ingredient.id)
+            .bind(ingredient.minQuantity)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    println!("equipment added");
+
+    Ok(())
+}
+
+// curl -X POST http://localhost:7474/gear_needed \
+//  -H "Content-Type: application/json" \
+//  -H "Authorization: Bearer <token>" \
+//  -d '{"characters": [{"baseId": "GENERALKENOBI", "goalGear": 13}]}'
@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::State;
+use serde::{Serialize, Deserialize};
+use sqlx::{SqlitePool, prelude::FromRow};
+use chrono::Utc;
+
+use crate::account::{seconds_until_refresh, sync_player_if_stale};
+use crate::auth::{AuthBearer, VerifiedUser};
+use crate::comlink::PlayerLookup;
+use crate::error::ApiError;
+use crate::extract::{Json, Query};
+use crate::roles::{Role, guild_role, is_admin, stored_guild};
+use crate::state::AppState;
+use crate::roster::get_player_from_db;
+use crate::types::{Guild, Player, relic_level};
+
+#[derive(Serialize, Deserialize)]
+pub struct GuildRoster {
+    pub guildId: String,
+    pub name: String,
+    pub memberCount: u32,
+    pub members: Vec<GuildMemberRoster>
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GuildMemberRoster {
+    pub memberLevel: u32,
+    #[serde(flatten)]
+    pub player: Player
+}
+
+/// The caller's guild as of the last `/guild/refresh`, each member with their `rosterUnit` data. Read only,
+/// it never asks comlink and answers 404 until an officer has refreshed the guild
+pub async fn guild(State(state): State<AppState>, AuthBearer(user): AuthBearer) -> Result<Json<GuildRoster>, ApiError> {
+    let pool = &state.pool;
+    let (guildId, _) = guild_role(&state, &user.allyCode).await?;
+
+    let (name, memberCount): (String, i64) = sqlx::query_as(r#"SELECT name, memberCount FROM guild WHERE guildId = ?"#)
+        .bind(&guildId)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Guild hasn't been synced yet, an officer can sync it with /guild/refresh".to_string()))?;
+
+    let memberRows: Vec<(String, i64)> = sqlx::query_as(r#"
+        SELECT allyCode, memberLevel FROM guildMember WHERE guildId = ? ORDER BY memberLevel DESC, playerName
+    "#)
+    .bind(&guildId)
+    .fetch_all(pool)
+    .await?;
+
+    let mut members: Vec<GuildMemberRoster> = Vec::new();
+    for (allyCode, memberLevel) in memberRows {
+        match get_player_from_db(&allyCode, pool).await {
+            Ok(player) => members.push(GuildMemberRoster { memberLevel: memberLevel as u32, player }),
+            Err(e) => eprintln!("Failed to load guild member {}: {}", allyCode, e)
+        }
+    }
+
+    Ok(Json(GuildRoster {
+        guildId,
+        name,
+        memberCount: memberCount as u32,
+        members
+    }))
+}
+
+/// POST /guild/refresh - officers only. Syncs the caller's guild from comlink, refreshing the roster of every
+/// member not synced within MIN_REFRESH_SECONDS on the way, and returns each member with their `rosterUnit` data.
+/// A guild can be refreshed once every GUILD_REFRESH_SECONDS
+pub async fn refresh_guild(State(state): State<AppState>, VerifiedUser(user): VerifiedUser) -> Result<Json<GuildRoster>, ApiError> {
+    let pool = &state.pool;
+    let (guildId, playerId) = stored_guild(&state, &user.allyCode).await?;
+    let admin = is_admin(&state, user.userId).await?;
+
+    // Callers are checked against the stored member list before comlink is asked. A guild that has never
+    // been refreshed has no list yet, so the pull below decides
+    let listed: bool = sqlx::query_scalar(r#"SELECT EXISTS(SELECT 1 FROM guildMember WHERE guildId = ?)"#)
+        .bind(&guildId)
+        .fetch_one(pool)
+        .await?;
+    if listed && !admin {
+        let (_, role) = guild_role(&state, &user.allyCode).await?;
+        if role < Role::Officer {
+            return Err(ApiError::Forbidden("Only guild officers and the leader can do this".to_string()));
+        }
+    }
+    check_guild_refresh_interval(&state, &guildId).await?;
+
+    let guild = state.comlink.guild(&guildId).await?;
+
+    // The member list just pulled has the final say, so a promotion or demotion in game counts straight away
+    let role = match admin {
+        true => Some(Role::Admin),
+        false => guild.member.iter()
+            .find(|member| member.playerId == playerId)
+            .and_then(|member| Role::from_member_level(member.memberLevel)),
+    };
+    if role.is_none_or(|role| role < Role::Officer) {
+        return Err(ApiError::Forbidden("Only guild officers and the leader can do this".to_string()));
+    }
+    println!("guild {} refresh by {} ({:?})", guildId, user.allyCode, role);
+
+    set_guild_database(&guild, pool).await?;
+
+    let mut members: Vec<GuildMemberRoster> = Vec::new();
+    for (num, member) in guild.member.iter().enumerate() {
+        println!("syncing guild member {} ({}/{})", member.playerName, num + 1, guild.member.len());
+
+        // One bad member shouldn't stop the rest of the guild from syncing
+        let player = match sync_player_if_stale(&state, &PlayerLookup::PlayerId(member.playerId.clone())).await {
+            Ok(player) => player,
+            Err(e) => {
+                eprintln!("Failed to sync guild member {}: {}", member.playerId, e);
+                continue;
+            }
+        };
+
+        sqlx::query(r#"
+            INSERT OR REPLACE INTO guildMember
+                (playerId, allyCode, playerName, memberLevel, guildId)
+            VALUES (?, ?, ?, ?, ?)
+        "#)
+        .bind(&member.playerId)
+        .bind(&player.allyCode)
+        .bind(&member.playerName)
+        .bind(member.memberLevel)
+        .bind(&guildId)
+        .execute(pool)
+        .await?;
+
+        members.push(GuildMemberRoster {
+            memberLevel: member.memberLevel,
+            player
+        });
+    }
+
+    Ok(Json(GuildRoster {
+        guildId,
+        name: guild.profile.name,
+        memberCount: guild.profile.memberCount,
+        members
+    }))
+}
+
+/// Stops one guild being pulled from comlink more often than GUILD_REFRESH_SECONDS
+async fn check_guild_refresh_interval(state: &AppState, guildId: &str) -> Result<(), ApiError> {
+    let lastUpdated: Option<String> = sqlx::query_scalar(r#"SELECT last_updated FROM guild WHERE guildId = ?"#)
+        .bind(guildId)
+        .fetch_optional(&state.pool)
+        .await?;
+
+    let wait = lastUpdated.map_or(0, |lastUpdated| seconds_until_refresh(&lastUpdated, state.config.guild_refresh_seconds));
+    if wait > 0 {
+        return Err(ApiError::TooManyRequests {
+            message: format!("Guild {} was refreshed recently, try again in {} seconds", guildId, wait),
+            retryAfter: wait as u64,
+        });
+    }
+
+    Ok(())
+}
+
+/// Stores the guild profile and clears its old member list, members who left drop out here
+/// and the current ones get written back as their rosters are synced
+async fn set_guild_database(guild: &Guild, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(r#"
+        INSERT OR REPLACE INTO guild (guildId, name, memberCount, last_updated)
+        VALUES (?, ?, ?, ?)
+    "#)
+    .bind(&guild.profile.id)
+    .bind(&guild.profile.name)
+    .bind(guild.profile.memberCount)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    sqlx::query(r#"DELETE FROM guildMember WHERE guildId = ?"#)
+        .bind(&guild.profile.id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct UnitMatrixQuery {
+    category: Option<String>
+}
+
+/// How many guild members own a unit, broken down by star, gear tier and relic tier.
+/// Each map is keyed by level with the number of members at exactly that level
+#[derive(Serialize, Deserialize)]
+pub struct UnitOwnership {
+    pub baseId: String,
+    pub owned: u32,
+    pub stars: BTreeMap<u32, u32>,
+    pub gear: BTreeMap<u32, u32>,
+    pub relic: BTreeMap<u32, u32>
+}
+
+#[derive(FromRow)]
+struct GuildUnitRow {
+    definitionId: String,
+    currentRarity: i64,
+    currentTier: i64,
+    relicTier: Option<i64>
+}
+
+/// Unit ownership matrix for the caller's guild, built from the last `/guild` sync.
+/// Every unit in the `unit` table is listed, optionally filtered by `?category=`
+pub async fn guild_units(
+    State(pool): State<SqlitePool>,
+    AuthBearer(user): AuthBearer,
+    Query(query): Query<UnitMatrixQuery>
+) -> Result<Json<Vec<UnitOwnership>>, ApiError> {
+    let guildId: String = sqlx::query_scalar(r#"SELECT guildId FROM account WHERE allyCode = ?"#)
+        .bind(&user.allyCode)
+        .fetch_optional(&pool)
+        .await?
+        .filter(|id: &String| !id.is_empty())
+        .ok_or_else(|| ApiError::NotFound("Player is not in a guild".to_string()))?;
+
+    let baseIds: Vec<String> = sqlx::query_scalar(r#"
+        SELECT baseId FROM unit
+        WHERE ?1 IS NULL OR baseId IN (SELECT baseId FROM unit_has_trait WHERE category_name = ?1)
+        ORDER BY baseId
+    "#)
+    .bind(&query.category)
+    .fetch_all(&pool)
+    .await?;
+
+    let rows: Vec<GuildUnitRow> = sqlx::query_as(r#"
+        SELECT rosterUnit.definitionId, rosterUnit.currentRarity, rosterUnit.currentTier, rosterUnit.relicTier
+        FROM rosterUnit INNER JOIN guildMember ON guildMember.allyCode = rosterUnit.allyCode
+        WHERE guildMember.guildId = ?
+    "#)
+    .bind(&guildId)
+    .fetch_all(&pool)
+    .await?;
+
+    let mut matrix: HashMap<String, UnitOwnership> = baseIds
+        .into_iter()
+        .map(|baseId| (baseId.clone(), UnitOwnership {
+            baseId,
+            owned: 0,
+            stars: BTreeMap::new(),
+            gear: BTreeMap::new(),
+            relic: BTreeMap::new()
+        }))
+        .collect();
+
+    for row in rows {
+        // Units filtered out by category (or missing from game data) are skipped
+        let Some(unit) = matrix.get_mut(&row.definitionId) else {
+            continue;
+        };
+
+        unit.owned += 1;
+        *unit.stars.entry(row.currentRarity as u32).or_default() += 1;
+        *unit.gear.entry(row.currentTier as u32).or_default() += 1;
+        if let Some(relic) = row.relicTier.and_then(relic_level) {
+            *unit.relic.entry(relic).or_default() += 1;
+        }
+    }
+
+    let mut units: Vec<UnitOwnership> = matrix.into_values().collect();
+    units.sort_by(|a, b| a.baseId.cmp(&b.baseId));
+
+    Ok(Json(units))
+}
+
+// curl http://localhost:7474/guild -H "Authorization: Bearer <token>"
+// curl "http://localhost:7474/guild/units?category=role_leader" -H "Authorization: Bearer <token>"
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use axum::extract::State;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Pool, prelude::FromRow};
+
+use crate::auth::VerifiedUser;
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::state::AppState;
+use crate::types::{Player, relic_level};
+
+type SnapshotRating = (i64, String, i64);
+type SnapshotUnitRow = (String, i64, i64, i64, Option<i64>);
+
+/// True when the player's rating and units are exactly what snapshot `snapshotId` holds
+async fn unchanged_since(player: &Player, pool: &Pool<Sqlite>, snapshotId: i64) -> Result<bool, sqlx::Error> {
+    let rating: SnapshotRating = sqlx::query_as(r#"SELECT skillRating, leagueId, divisionId FROM rosterSnapshot WHERE id = ?"#)
+        .bind(snapshotId)
+        .fetch_one(pool)
+        .await?;
+    let current: SnapshotRating = (
+        player.playerRating.playerSkillRating.skillRating as i64,
+        player.playerRating.playerRankStatus.leagueId.clone(),
+        player.playerRating.playerRankStatus.divisionId as i64,
+    );
+    if rating != current {
+        return Ok(false);
+    }
+
+    let mut stored: Vec<SnapshotUnitRow> = sqlx::query_as(r#"
+        SELECT definitionId, currentRarity, currentLevel, currentTier, relicTier FROM snapshotUnit WHERE snapshotId = ?
+    "#)
+    .bind(snapshotId)
+    .fetch_all(pool)
+    .await?;
+    let mut units: HashMap<String, SnapshotUnitRow> = HashMap::new();
+    for unit in &player.rosterUnit {
+        let baseId = unit.definitionId.split(":").next().unwrap_or(&unit.definitionId).to_string();
+        let row = (baseId.clone(), unit.currentRarity as i64, unit.currentLevel as i64, unit.currentTier as i64, unit.relic.as_ref().map(|r| r.currentTier as i64));
+        units.insert(baseId, row);
+    }
+    let mut units: Vec<SnapshotUnitRow> = units.into_values().collect();
+    stored.sort();
+    units.sort();
+
+    Ok(stored == units)
+}
+
+/// Copies the roster and rating into a new snapshot, called on every pull from comlink
+/// since rosterUnit only ever holds the latest state. A pull that changed nothing since the
+/// last snapshot isn't stored and gives None. Snapshots older than `retentionDays` are pruned
+/// when a new one is saved, except the one it follows so the latest diff still works. 0 keeps them all
+pub async fn save_snapshot(player: &Player, pool: &Pool<Sqlite>, retentionDays: i64) -> Result<Option<i64>, sqlx::Error> {
+    let previous = latest_snapshot(pool, &player.allyCode).await?;
+    if let Some((previousId, _)) = &previous
+        && unchanged_since(player, pool, *previousId).await?
+    {
+        println!("roster of {} unchanged since snapshot {}", player.allyCode, previousId);
+        return Ok(None);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let snapshotId: i64 = sqlx::query_scalar(
+        r#"
+        INSERT INTO rosterSnapshot (allyCode, takenAt, skillRating, leagueId, divisionId)
+        VALUES (?, ?, ?, ?, ?)
+        RETURNING id
+        "#
+    )
+    .bind(&player.allyCode)
+    .bind(Utc::now().to_rfc3339())
+    .bind(player.playerRating.playerSkillRating.skillRating as i64)
+    .bind(&player.playerRating.playerRankStatus.leagueId)
+    .bind(player.playerRating.playerRankStatus.divisionId as i64)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    for unit in &player.rosterUnit {
+        let baseId = unit.definitionId.split(":").next().unwrap_or(&unit.definitionId);
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO snapshotUnit (
+                snapshotId, definitionId, currentRarity, currentLevel, currentTier, relicTier
+            ) VALUES (?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(snapshotId)
+        .bind(baseId)
+        .bind(unit.currentRarity)
+        .bind(unit.currentLevel)
+        .bind(unit.currentTier)
+        .bind(unit.relic.as_ref().map(|r| r.currentTier as i64))
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    if retentionDays > 0 {
+        let cutoff = (Utc::now() - chrono::Duration::days(retentionDays)).to_rfc3339();
+        let keepFrom = previous.map(|(id, _)| id).unwrap_or(snapshotId);
+        sqlx::query(r#"
+            DELETE FROM snapshotUnit WHERE snapshotId IN (
+                SELECT id FROM rosterSnapshot WHERE allyCode = ? AND takenAt < ? AND id < ?
+            )
+        "#)
+        .bind(&player.allyCode)
+        .bind(&cutoff)
+        .bind(keepFrom)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(r#"DELETE FROM rosterSnapshot WHERE allyCode = ? AND takenAt < ? AND id < ?"#)
+            .bind(&player.allyCode)
+            .bind(&cutoff)
+            .bind(keepFrom)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    println!("saved roster snapshot {} for {}", snapshotId, player.allyCode);
+
+    Ok(Some(snapshotId))
+}
+
+/// `since` and `until` take a date (2024-05-01) or a full RFC 3339 time, both ends are inclusive
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    since: Option<String>,
+    until: Option<String>
+}
+
+impl HistoryQuery {
+    /// The range as RFC 3339 UTC strings so it compares with takenAt. A bare `until` date covers the whole day
+    fn range(&self) -> Result<(String, String), ApiError> {
+        let since = match &self.since {
+            Some(since) => parse_time(since, false)?,
+            None => String::new(),
+        };
+        let until = match &self.until {
+            Some(until) => parse_time(until, true)?,
+            None => parse_time(&Utc::now().date_naive().to_string(), true)?,
+        };
+        Ok((since, until))
+    }
+}
+
+fn parse_time(value: &str, endOfDay: bool) -> Result<String, ApiError> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc).to_rfc3339());
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| ApiError::BadRequest(format!("{} is not a date like 2024-05-01", value)))?;
+    let time = if endOfDay {
+        date.and_hms_nano_opt(23, 59, 59, 999_999_999)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    Ok(time.unwrap().and_utc().to_rfc3339())
+}
+
+/// Totals for the whole roster at one snapshot
+#[derive(Serialize, FromRow)]
+pub struct AccountSnapshot {
+    pub takenAt: String,
+    pub skillRating: i64,
+    pub leagueId: String,
+    pub divisionId: i64,
+    pub units: i64,
+    pub totalStars: i64,
+    pub sevenStars: i64,
+    pub gear13: i64,
+    /// Sum of every unit's relic level
+    pub relics: i64
+}
+
+/// How much the account changed between the first and last snapshot in the range
+#[derive(Serialize)]
+pub struct AccountGrowth {
+    pub from: String,
+    pub to: String,
+    pub skillRating: i64,
+    pub units: i64,
+    pub totalStars: i64,
+    pub sevenStars: i64,
+    pub gear13: i64,
+    pub relics: i64
+}
+
+#[derive(Serialize)]
+pub struct AccountHistory {
+    pub allyCode: String,
+    pub snapshots: Vec<AccountSnapshot>,
+    /// None when there are no snapshots in the range
+    pub growth: Option<AccountGrowth>
+}
+
+/// GET /history - the account's growth curve, one point per sync that changed something, oldest first
+pub async fn account_history(
+    State(state): State<AppState>,
+    VerifiedUser(user): VerifiedUser,
+    Query(query): Query<HistoryQuery>
+) -> Result<Json<AccountHistory>, ApiError> {
+    let (since, until) = query.range()?;
+
+    let snapshots = sqlx::query_as::<_, AccountSnapshot>(r#"
+        SELECT rosterSnapshot.takenAt, rosterSnapshot.skillRating, rosterSnapshot.leagueId, rosterSnapshot.divisionId,
+            COUNT(snapshotUnit.definitionId) AS units,
+            COALESCE(SUM(snapshotUnit.currentRarity), 0) AS totalStars,
+            COALESCE(SUM(snapshotUnit.currentRarity = 7), 0) AS sevenStars,
+            COALESCE(SUM(snapshotUnit.currentTier = 13), 0) AS gear13,
+            COALESCE(SUM(CASE WHEN snapshotUnit.relicTier >= 2 THEN snapshotUnit.relicTier - 2 ELSE 0 END), 0) AS relics
+        FROM rosterSnapshot
+            LEFT JOIN snapshotUnit ON snapshotUnit.snapshotId = rosterSnapshot.id
+        WHERE rosterSnapshot.allyCode = ? AND rosterSnapshot.takenAt >= ? AND rosterSnapshot.takenAt <= ?
+        GROUP BY rosterSnapshot.id
+        ORDER BY rosterSnapshot.takenAt, rosterSnapshot.id
+    "#)
+    .bind(&user.allyCode)
+    .bind(&since)
+    .bind(&until)
+    .fetch_all(&state.pool)
+    .await?;
+
+    let growth = match (snapshots.first(), snapshots.last()) {
+        (Some(first), Some(last)) => Some(AccountGrowth {
+            from: first.takenAt.clone(),
+            to: last.takenAt.clone(),
+            skillRating: last.skillRating - first.skillRating,
+            units: last.units - first.units,
+            totalStars: last.totalStars - first.totalStars,
+            sevenStars: last.sevenStars - first.sevenStars,
+            gear13: last.gear13 - first.gear13,
+            relics: last.relics - first.relics,
+        }),
+        _ => None,
+    };
+
+    Ok(Json(AccountHistory { allyCode: user.allyCode, snapshots, growth }))
+}
+
+#[derive(FromRow)]
+struct UnitRow {
+    takenAt: String,
+    currentRarity: i64,
+    currentLevel: i64,
+    currentTier: i64,
+    relicTier: Option<i64>
+}
+
+/// A unit's state from `takenAt` until the next point
+#[derive(Serialize)]
+pub struct UnitProgress {
+    pub takenAt: String,
+    pub stars: i64,
+    pub level: i64,
+    pub gear: i64,
+    /// None until relics are unlocked
+    pub relic: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct UnitHistory {
+    pub baseId: String,
+    pub progress: Vec<UnitProgress>
+}
+
+/// GET /history/{baseId} - when the unit was unlocked and every time its stars, level, gear or relic changed
+pub async fn unit_history(
+    State(state): State<AppState>,
+    VerifiedUser(user): VerifiedUser,
+    Path(baseId): Path<String>,
+    Query(query): Query<HistoryQuery>
+) -> Result<Json<UnitHistory>, ApiError> {
+    let (since, until) = query.range()?;
+
+    let rows = sqlx::query_as::<_, UnitRow>(r#"
+        SELECT rosterSnapshot.takenAt, snapshotUnit.currentRarity, snapshotUnit.currentLevel,
+            snapshotUnit.currentTier, snapshotUnit.relicTier
+        FROM snapshotUnit
+            JOIN rosterSnapshot ON rosterSnapshot.id = snapshotUnit.snapshotId
+        WHERE rosterSnapshot.allyCode = ? AND snapshotUnit.definitionId = ?
+            AND rosterSnapshot.takenAt >= ? AND rosterSnapshot.takenAt <= ?
+        ORDER BY rosterSnapshot.takenAt, rosterSnapshot.id
+    "#)
+    .bind(&user.allyCode)
+    .bind(&baseId)
+    .bind(&since)
+    .bind(&until)
+    .fetch_all(&state.pool)
+    .await?;
+
+    if rows.is_empty() {
+        return Err(ApiError::NotFound(format!("No history for {}", baseId)));
+    }
+
+    // Most refreshes don't touch a given unit, only keep the points where something changed
+    let mut progress: Vec<UnitProgress> = Vec::new();
+    for row in rows {
+        let point = UnitProgress {
+            takenAt: row.takenAt,
+            stars: row.currentRarity,
+            level: row.currentLevel,
+            gear: row.currentTier,
+            relic: row.relicTier.and_then(relic_level),
+        };
+        let unchanged = progress.last().is_some_and(|last| {
+            (last.stars, last.level, last.gear, last.relic) == (point.stars, point.level, point.gear, point.relic)
+        });
+        if !unchanged {
+            progress.push(point);
+        }
+    }
+
+    Ok(Json(UnitHistory { baseId, progress }))
+}
+
+/// One unit moving from one value to another, `from` is None when relics or the unit itself were locked
+#[derive(Serialize)]
+pub struct UnitChange {
+    pub baseId: String,
+    pub from: Option<i64>,
+    pub to: i64
+}
+
+/// What changed on the roster between two snapshots
+#[derive(Serialize)]
+pub struct RosterDiff {
+    /// None when `to` is the first snapshot, then every unit counts as unlocked
+    pub from: Option<String>,
+    pub to: String,
+    /// The star level each new unit was unlocked at
+    pub unlocked: Vec<UnitChange>,
+    pub stars: Vec<UnitChange>,
+    pub gear: Vec<UnitChange>,
+    /// Relic levels as shown in game
+    pub relics: Vec<UnitChange>
+}
+
+struct SnapshotUnit {
+    currentRarity: i64,
+    currentTier: i64,
+    relic: Option<u32>
+}
+
+/// The last snapshot of the ally code taken at or before `at`, or the latest one
+async fn snapshot_at(pool: &Pool<Sqlite>, allyCode: &str, at: Option<&str>) -> Result<Option<(i64, String)>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT id, takenAt FROM rosterSnapshot
+        WHERE allyCode = ? AND (? IS NULL OR takenAt <= ?)
+        ORDER BY takenAt DESC, id DESC
+        LIMIT 1
+    "#)
+    .bind(allyCode)
+    .bind(at)
+    .bind(at)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The snapshot taken just before `snapshotId`
+async fn snapshot_before(pool: &Pool<Sqlite>, allyCode: &str, snapshotId: i64) -> Result<Option<(i64, String)>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT id, takenAt FROM rosterSnapshot
+        WHERE allyCode = ? AND id < ?
+        ORDER BY id DESC
+        LIMIT 1
+    "#)
+    .bind(allyCode)
+    .bind(snapshotId)
+    .fetch_optional(pool)
+    .await
+}
+
+async fn snapshot_units(pool: &Pool<Sqlite>, snapshotId: i64) -> Result<HashMap<String, SnapshotUnit>, sqlx::Error> {
+    let rows: Vec<(String, i64, i64, Option<i64>)> = sqlx::query_as(r#"
+        SELECT definitionId, currentRarity, currentTier, relicTier FROM snapshotUnit WHERE snapshotId = ?
+    "#)
+    .bind(snapshotId)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(baseId, currentRarity, currentTier, relicTier)| {
+            (baseId, SnapshotUnit { currentRarity, currentTier, relic: relicTier.and_then(relic_level) })
+        })
+        .collect())
+}
+
+/// Compares two snapshots, `from` None compares against an empty roster
+async fn diff_snapshots(pool: &Pool<Sqlite>, from: Option<(i64, String)>, to: (i64, String)) -> Result<RosterDiff, sqlx::Error> {
+    let before = match &from {
+        Some((snapshotId, _)) => snapshot_units(pool, *snapshotId).await?,
+        None => HashMap::new(),
+    };
+    let after = snapshot_units(pool, to.0).await?;
+
+    let mut diff = RosterDiff {
+        from: from.map(|(_, takenAt)| takenAt),
+        to: to.1,
+        unlocked: Vec::new(),
+        stars: Vec::new(),
+        gear: Vec::new(),
+        relics: Vec::new(),
+    };
+
+    let mut baseIds: Vec<&String> = after.keys().collect();
+    baseIds.sort();
+    for baseId in baseIds {
+        let unit = &after[baseId];
+        let Some(old) = before.get(baseId) else {
+            diff.unlocked.push(UnitChange { baseId: baseId.clone(), from: None, to: unit.currentRarity });
+            continue;
+        };
+
+        if unit.currentRarity != old.currentRarity {
+            diff.stars.push(UnitChange { baseId: baseId.clone(), from: Some(old.currentRarity), to: unit.currentRarity });
+        }
+        if unit.currentTier != old.currentTier {
+            diff.gear.push(UnitChange { baseId: baseId.clone(), from: Some(old.currentTier), to: unit.currentTier });
+        }
+        if let Some(relic) = unit.relic.filter(|relic| Some(*relic) != old.relic) {
+            diff.relics.push(UnitChange { baseId: baseId.clone(), from: old.relic.map(i64::from), to: relic as i64 });
+        }
+    }
+
+    Ok(diff)
+}
+
+/// The ally code's most recent snapshot, None before the first sync
+pub async fn latest_snapshot(pool: &Pool<Sqlite>, allyCode: &str) -> Result<Option<(i64, String)>, sqlx::Error> {
+    snapshot_at(pool, allyCode, None).await
+}
+
+/// What changed from `since`, the latest snapshot before a sync, to the latest one now.
+/// Empty when the sync didn't save a new snapshot, None if there are no snapshots at all
+pub async fn changes_since(pool: &Pool<Sqlite>, allyCode: &str, since: Option<(i64, String)>) -> Result<Option<RosterDiff>, sqlx::Error> {
+    let Some(to) = latest_snapshot(pool, allyCode).await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(diff_snapshots(pool, since, to).await?))
+}
+
+/// `from` and `to` pick the last snapshot taken on or before each, a bare date counts the whole day.
+/// Without `to` the latest snapshot is used and without `from` the one before `to`
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    from: Option<String>,
+    to: Option<String>
+}
+
+/// GET /history/diff - units unlocked, promoted, geared or given relics between two syncs
+pub async fn roster_diff(
+    State(state): State<AppState>,
+    VerifiedUser(user): VerifiedUser,
+    Query(query): Query<DiffQuery>
+) -> Result<Json<RosterDiff>, ApiError> {
+    let toTime = query.to.as_deref().map(|to| parse_time(to, true)).transpose()?;
+    let to = snapshot_at(&state.pool, &user.allyCode, toTime.as_deref())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No roster snapshot at that time".to_string()))?;
+
+    let from = match &query.from {
+        Some(from) => {
+            let fromTime = parse_time(from, true)?;
+            snapshot_at(&state.pool, &user.allyCode, Some(&fromTime)).await?
+        }
+        None => snapshot_before(&state.pool, &user.allyCode, to.0).await?,
+    };
+
+    Ok(Json(diff_snapshots(&state.pool, from, to).await?))
+}
+
+//curl http://localhost:7474/history?since=2024-05-01 -H "Authorization: Bearer <token>"
+//curl http://localhost:7474/history/GRANDMASTERYODA -H "Authorization: Bearer <token>"
+//curl "http://localhost:7474/history/diff?from=2024-05-01" -H "Authorization: Bearer <token>"
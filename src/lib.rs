@@ -0,0 +1,144 @@
+#![allow(non_snake_case)]
+use std::{path::PathBuf, time::Duration};
+
+use axum::{
+    Router, middleware, routing::{delete, get, get_service, post, put}
+};
+use tower_http::{cors::CorsLayer, services::ServeDir};
+
+pub mod error;
+pub mod extract;
+pub mod comlink;
+pub mod ratelimit;
+pub mod mailer;
+pub mod state;
+pub mod types;
+pub mod setup;
+pub mod gamedata;
+
+pub mod auth;
+pub mod account;
+pub mod characters;
+pub mod roster;
+pub mod plan;
+pub mod gear;
+pub mod stars;
+pub mod relic;
+pub mod guild;
+pub mod verify;
+pub mod roles;
+pub mod admin;
+pub mod history;
+pub mod mods;
+pub mod stats;
+pub mod optimizer;
+
+use state::AppState;
+use ratelimit::{RateLimiter, rate_limit};
+use auth::{signIn, signUp, refresh, signOut, change_password, forgot_password, reset_password, delete_user};
+use account::{account, refresh_account_handler, list_accounts, add_account, remove_account, set_default_account};
+use characters::characters;
+use plan::{
+    set_plan, get_plan, update_plan, patch_plan, delete_plan, order_plans,
+    add_char_plan, update_char_plan, patch_char_plan, delete_char_plan, order_char_plans
+};
+use gear::gear_needed;
+use stars::shards_needed;
+use relic::relics_needed;
+use guild::{guild, guild_units, refresh_guild};
+use admin::{sync_game_data, list_users, remove_user, set_admin};
+use verify::{verification_status, create_challenge, verify};
+use history::{account_history, unit_history, roster_diff};
+use mods::{mods, mod_summary};
+use optimizer::optimize_mods;
+
+//endpoints - 
+//characters - all character names, skills, image, id - charId just sends one
+//account - account information, all character gear, star, relic
+//guild - accounts guild, with number of each character unlocked, each user fleet, squad, and GAC rank - charId returns dictionary of all characters at every level
+//journey - journey guide information
+
+/// Every route the API serves, kept out of `main` so tests can drive it without binding a port
+pub fn app(state: AppState) -> Router {
+    let image_dir = PathBuf::from("./assets");
+
+    // let cors = CorsLayer::new()
+    // .allow_origin(tower_http::cors::Any)
+    // .allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
+    // .allow_headers(Any);
+
+    let cors = CorsLayer::permissive();
+
+    // Everything here can end up calling comlink, is worth guessing at or is slow to answer, so callers are held to
+    // RATE_LIMIT_PER_MINUTE requests a minute per user and RATE_LIMIT_PER_IP_MINUTE per IP across all of them
+    let limiter = RateLimiter::new(
+        state.config.rate_limit_per_minute,
+        state.config.rate_limit_per_ip_minute,
+        Duration::from_secs(60),
+        state.config.jwt_secret.clone()
+    );
+    let limited = Router::new()
+        .route("/account", get(account))
+        .route("/refreshAccount", get(refresh_account_handler))
+        .route("/accounts", get(list_accounts).post(add_account))
+        .route("/guild/refresh", post(refresh_guild))
+        .route("/signUp", post(signUp))
+        .route("/signIn", post(signIn))
+        .route("/password/forgot", post(forgot_password))
+        .route("/verify", get(verification_status).post(verify))
+        .route("/verify/challenge", post(create_challenge))
+        .route("/mods/optimize", post(optimize_mods))
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit));
+
+    Router::new()
+        .route("/", get(root))
+        .route("/characters", post(characters))
+        .route("/guild", get(guild))
+        .route("/guild/units", get(guild_units))
+        .route("/admin/gamedata/sync", post(sync_game_data))
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/{userId}", delete(remove_user))
+        .route("/admin/users/{userId}/admin", put(set_admin))
+        .route("/accounts/{allyCode}", delete(remove_account))
+        .route("/accounts/{allyCode}/default", put(set_default_account))
+        .route("/refresh", post(refresh))
+        .route("/signOut", post(signOut))
+        .route("/password", post(change_password))
+        .route("/password/reset", post(reset_password))
+        .route("/user", delete(delete_user))
+        .route("/history", get(account_history))
+        .route("/history/diff", get(roster_diff))
+        .route("/history/{baseId}", get(unit_history))
+        .route("/mods", get(mods))
+        .route("/mods/summary", get(mod_summary))
+        .route("/set_plan", post(set_plan))
+        .route("/get_plan", get(get_plan))
+        .route("/gear_needed", post(gear_needed))
+        .route("/shards_needed", post(shards_needed))
+        .route("/relics_needed", post(relics_needed))
+        .route("/plans/order", put(order_plans))
+        .route("/plan/{planId}", put(update_plan).patch(patch_plan).delete(delete_plan))
+        .route("/plan/{planId}/order", put(order_char_plans))
+        .route("/plan/{planId}/character", post(add_char_plan))
+        .route("/plan/{planId}/character/{charPlanId}", put(update_char_plan).patch(patch_char_plan).delete(delete_char_plan))
+        .nest(
+            "/assets",
+            Router::new()
+                .fallback_service(
+                    get_service(ServeDir::new(image_dir))
+                        .handle_error(|error| async move {
+                            (
+                                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                                format!("Unhandled internal error: {}", error),
+                            )
+                        }),
+                ),
+        )
+        .merge(limited)
+        .layer(cors)
+        .with_state(state)
+}
+
+async fn root() -> &'static str{
+    "Hello World"
+}
@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqlitePool, prelude::FromRow};
+
+use crate::auth::VerifiedUser;
+use crate::error::ApiError;
+use crate::extract::{Json, Query};
+
+/// Mod sets by the first digit of a mod's definitionId
+pub const MOD_SETS: [(u32, &str); 8] = [
+    (1, "health"), (2, "offense"), (3, "defense"), (4, "speed"),
+    (5, "critchance"), (6, "critdamage"), (7, "potency"), (8, "tenacity")
+];
+
+/// Mod slots by the last digit of a mod's definitionId
+pub const MOD_SLOTS: [(u32, &str); 6] = [
+    (1, "square"), (2, "arrow"), (3, "diamond"), (4, "triangle"), (5, "circle"), (6, "cross")
+];
+
+/// The unitStatIds mods can roll, percentages end in %
+pub const MOD_STATS: [(u32, &str); 15] = [
+    (1, "health"), (5, "speed"), (16, "critdamage%"), (17, "potency%"), (18, "tenacity%"),
+    (28, "protection"), (41, "offense"), (42, "defense"), (48, "offense%"), (49, "defense%"),
+    (52, "accuracy%"), (53, "critchance%"), (54, "critavoidance%"), (55, "health%"), (56, "protection%")
+];
+
+pub const SPEED_STAT_ID: u32 = 5;
+
+/// comlink sends stat values times 10000
+const STAT_SCALE: f64 = 10000.0;
+
+pub fn name_of(table: &[(u32, &'static str)], id: u32) -> &'static str {
+    table.iter().find(|(known, _)| *known == id).map(|(_, name)| *name).unwrap_or("unknown")
+}
+
+/// Reads a filter given as the id or the name
+fn id_of(table: &[(u32, &str)], value: &str, what: &str) -> Result<u32, ApiError> {
+    let value = value.trim().to_lowercase();
+    table.iter()
+        .find(|(id, name)| *name == value || id.to_string() == value)
+        .map(|(id, _)| *id)
+        .ok_or_else(|| {
+            let names: Vec<&str> = table.iter().map(|(_, name)| *name).collect();
+            ApiError::BadRequest(format!("Unknown {} {}, expected one of {}", what, value, names.join(", ")))
+        })
+}
+
+#[derive(Serialize)]
+pub struct StatValue {
+    pub statId: u32,
+    pub stat: &'static str,
+    pub value: f64,
+    /// Secondaries only
+    pub rolls: Option<u32>
+}
+
+#[derive(Serialize)]
+pub struct ModView {
+    pub id: String,
+    /// The unit wearing it
+    pub baseId: String,
+    pub set: &'static str,
+    pub slot: &'static str,
+    #[serde(skip)]
+    pub setId: u32,
+    #[serde(skip)]
+    pub slotId: u32,
+    pub rarity: i64,
+    pub level: i64,
+    pub tier: i64,
+    pub primaryStat: StatValue,
+    pub secondaryStats: Vec<StatValue>,
+    /// The speed secondary, 0 without one
+    pub speed: f64
+}
+
+#[derive(FromRow)]
+struct ModRow {
+    id: String,
+    baseId: String,
+    setId: i64,
+    slot: i64,
+    rarity: i64,
+    level: i64,
+    tier: i64,
+    primaryStatId: i64,
+    primaryValue: i64,
+    speed: i64
+}
+
+/// Filters are optional. set, slot and primary take a name from MOD_SETS, MOD_SLOTS and MOD_STATS or the number
+#[derive(Deserialize, Default)]
+pub struct ModQuery {
+    set: Option<String>,
+    slot: Option<String>,
+    primary: Option<String>,
+    /// Only mods with at least this much secondary speed
+    minSpeed: Option<f64>,
+    baseId: Option<String>
+}
+
+/// Loads the ally code's mods that pass the filters, fastest speed secondary first
+pub(crate) async fn load_mods(pool: &SqlitePool, allyCode: &str, query: &ModQuery) -> Result<Vec<ModView>, ApiError> {
+    let setId = query.set.as_deref().map(|set| id_of(&MOD_SETS, set, "set")).transpose()?;
+    let slot = query.slot.as_deref().map(|slot| id_of(&MOD_SLOTS, slot, "slot")).transpose()?;
+    let primary = query.primary.as_deref().map(|primary| id_of(&MOD_STATS, primary, "stat")).transpose()?;
+    let minSpeed = query.minSpeed.map(|speed| (speed * STAT_SCALE).round() as i64);
+
+    let rows = sqlx::query_as::<_, ModRow>(r#"
+        SELECT mod.id, mod.baseId, mod.setId, mod.slot, mod.rarity, mod.level, mod.tier,
+            mod.primaryStatId, mod.primaryValue, COALESCE(speed.value, 0) AS speed
+        FROM mod
+            LEFT JOIN modStat speed ON speed.modId = mod.id AND speed.statId = ?
+        WHERE mod.allyCode = ?
+            AND (? IS NULL OR mod.setId = ?)
+            AND (? IS NULL OR mod.slot = ?)
+            AND (? IS NULL OR mod.primaryStatId = ?)
+            AND (? IS NULL OR COALESCE(speed.value, 0) >= ?)
+            AND (? IS NULL OR mod.baseId = ?)
+        ORDER BY speed DESC, mod.setId, mod.slot, mod.id
+    "#)
+    .bind(SPEED_STAT_ID)
+    .bind(allyCode)
+    .bind(setId).bind(setId)
+    .bind(slot).bind(slot)
+    .bind(primary).bind(primary)
+    .bind(minSpeed).bind(minSpeed)
+    .bind(&query.baseId).bind(&query.baseId)
+    .fetch_all(pool)
+    .await?;
+
+    let statRows: Vec<(String, i64, i64, i64)> = sqlx::query_as(r#"
+        SELECT modStat.modId, modStat.statId, modStat.value, modStat.rolls
+        FROM modStat
+            JOIN mod ON mod.id = modStat.modId
+        WHERE mod.allyCode = ?
+        ORDER BY modStat.statId
+    "#)
+    .bind(allyCode)
+    .fetch_all(pool)
+    .await?;
+    let mut secondaries: HashMap<String, Vec<StatValue>> = HashMap::new();
+    for (modId, statId, value, rolls) in statRows {
+        secondaries.entry(modId).or_default().push(StatValue {
+            statId: statId as u32,
+            stat: name_of(&MOD_STATS, statId as u32),
+            value: value as f64 / STAT_SCALE,
+            rolls: Some(rolls as u32),
+        });
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ModView {
+            secondaryStats: secondaries.remove(&row.id).unwrap_or_default(),
+            id: row.id,
+            baseId: row.baseId,
+            set: name_of(&MOD_SETS, row.setId as u32),
+            slot: name_of(&MOD_SLOTS, row.slot as u32),
+            setId: row.setId as u32,
+            slotId: row.slot as u32,
+            rarity: row.rarity,
+            level: row.level,
+            tier: row.tier,
+            primaryStat: StatValue {
+                statId: row.primaryStatId as u32,
+                stat: name_of(&MOD_STATS, row.primaryStatId as u32),
+                value: row.primaryValue as f64 / STAT_SCALE,
+                rolls: None,
+            },
+            speed: row.speed as f64 / STAT_SCALE,
+        })
+        .collect())
+}
+
+/// GET /mods - the account's equipped mods as of the last sync, see ModQuery for the filters
+pub async fn mods(
+    State(pool): State<SqlitePool>,
+    VerifiedUser(user): VerifiedUser,
+    Query(query): Query<ModQuery>
+) -> Result<Json<Vec<ModView>>, ApiError> {
+    Ok(Json(load_mods(&pool, &user.allyCode, &query).await?))
+}
+
+#[derive(Serialize)]
+pub struct ModSummary {
+    pub total: i64,
+    /// 6 dot mods
+    pub sixDot: i64,
+    pub bySet: BTreeMap<&'static str, i64>,
+    /// Mods with a speed secondary of at least 10, 15 and 20
+    pub speed10: i64,
+    pub speed15: i64,
+    pub speed20: i64
+}
+
+/// GET /mods/summary - mod counts by set and how many have fast speed secondaries
+pub async fn mod_summary(State(pool): State<SqlitePool>, VerifiedUser(user): VerifiedUser) -> Result<Json<ModSummary>, ApiError> {
+    let (total, sixDot, speed10, speed15, speed20): (i64, i64, i64, i64, i64) = sqlx::query_as(r#"
+        SELECT COUNT(*),
+            COALESCE(SUM(mod.rarity = 6), 0),
+            COALESCE(SUM(COALESCE(speed.value, 0) >= 100000), 0),
+            COALESCE(SUM(COALESCE(speed.value, 0) >= 150000), 0),
+            COALESCE(SUM(COALESCE(speed.value, 0) >= 200000), 0)
+        FROM mod
+            LEFT JOIN modStat speed ON speed.modId = mod.id AND speed.statId = ?
+        WHERE mod.allyCode = ?
+    "#)
+    .bind(SPEED_STAT_ID)
+    .bind(&user.allyCode)
+    .fetch_one(&pool)
+    .await?;
+
+    let sets: Vec<(i64, i64)> = sqlx::query_as(r#"SELECT setId, COUNT(*) FROM mod WHERE allyCode = ? GROUP BY setId"#)
+        .bind(&user.allyCode)
+        .fetch_all(&pool)
+        .await?;
+    let bySet = sets.into_iter().map(|(setId, count)| (name_of(&MOD_SETS, setId as u32), count)).collect();
+
+    Ok(Json(ModSummary { total, sixDot, bySet, speed10, speed15, speed20 }))
+}
+
+//curl "http://localhost:7474/mods?set=speed&slot=arrow&primary=speed&minSpeed=10" -H "Authorization: Bearer <token>"
+//curl http://localhost:7474/mods/summary -H "Authorization: Bearer <token>"
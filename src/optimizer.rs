@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::VerifiedUser;
+use crate::error::{ApiError, ValidationError};
+use crate::extract::Json;
+use crate::mods::{ModQuery, ModView, load_mods};
+use crate::stats::{SET_BONUSES, STAT_NAMES, StatValues, Stats, base_stats, calculate, mod_stats, named, set_stats, stat_index};
+
+/// Each character tries every set layout, so keep requests to a size that answers quickly
+const MAX_CHARACTERS: usize = 20;
+/// Best mods kept per slot and set while searching. More than one lets a loadout give up the top mod in a slot
+/// to finish a set, get a maxed set's full bonus or reach a minimum
+const CANDIDATES: usize = 3;
+const SLOTS: [u32; 6] = [1, 2, 3, 4, 5, 6];
+
+/// `weights` multiply each stat in the objective and `minimums` are stats the loadout has to reach,
+/// both keyed by the names in STAT_NAMES. Stats are the unit's own with mods on, percentages are fractions, 0.5 is 50%
+#[derive(Deserialize)]
+pub struct OptimizeGoal {
+    baseId: String,
+    #[serde(default)]
+    weights: BTreeMap<String, f64>,
+    #[serde(default)]
+    minimums: BTreeMap<String, f64>
+}
+
+/// Characters in priority order, earlier ones get first pick of the mods
+#[derive(Deserialize)]
+pub struct OptimizePayload {
+    characters: Vec<OptimizeGoal>
+}
+
+#[derive(Serialize)]
+pub struct ModMove {
+    pub modId: String,
+    pub set: &'static str,
+    pub slot: &'static str,
+    pub from: String,
+    pub to: String
+}
+
+#[derive(Serialize)]
+pub struct Loadout {
+    pub baseId: String,
+    pub mods: Vec<String>,
+    /// Stats with the mods the unit has on now and with the new loadout
+    pub before: Stats,
+    pub after: Stats,
+    /// Minimums the best loadout still misses
+    pub unmet: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct OptimizeResult {
+    pub characters: Vec<Loadout>,
+    /// Every mod that has to change units, in the order of `characters`
+    pub moves: Vec<ModMove>
+}
+
+/// POST /mods/optimize - assigns mods from the whole roster to the listed characters and returns the moves to get there
+pub async fn optimize_mods(
+    State(pool): State<SqlitePool>,
+    VerifiedUser(user): VerifiedUser,
+    Json(payload): Json<OptimizePayload>
+) -> Result<Json<OptimizeResult>, ApiError> {
+    if payload.characters.is_empty() || payload.characters.len() > MAX_CHARACTERS {
+        return Err(ApiError::BadRequest(format!("Send between 1 and {} characters", MAX_CHARACTERS)));
+    }
+
+    let tiers: HashMap<String, i64> = sqlx::query_as(r#"SELECT definitionId, currentTier FROM rosterUnit WHERE allyCode = ?"#)
+        .bind(&user.allyCode)
+        .fetch_all(&pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    let errors = goal_errors(&payload.characters, &tiers);
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let mut bases: Vec<StatValues> = Vec::new();
+    for goal in &payload.characters {
+        bases.push(base_stats(&pool, &goal.baseId, tiers[&goal.baseId]).await?);
+    }
+    let mods = load_mods(&pool, &user.allyCode, &ModQuery::default()).await?;
+
+    // The search is plain CPU work, keep it off the async workers
+    let result = tokio::task::spawn_blocking(move || optimize(&payload.characters, &bases, &mods))
+        .await
+        .map_err(|e| ApiError::Internal(format!("mod optimizer failed: {}", e)))?;
+
+    Ok(Json(result))
+}
+
+fn goal_errors(goals: &[OptimizeGoal], tiers: &HashMap<String, i64>) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    let error = |index: usize, goal: &OptimizeGoal, field: String, message: String| ValidationError {
+        index,
+        baseId: goal.baseId.clone(),
+        field,
+        message
+    };
+
+    for (index, goal) in goals.iter().enumerate() {
+        if !tiers.contains_key(&goal.baseId) {
+            errors.push(error(index, goal, "baseId".to_string(), format!("{} is not on the roster", goal.baseId)));
+        } else if !seen.insert(&goal.baseId) {
+            errors.push(error(index, goal, "baseId".to_string(), format!("{} is listed more than once", goal.baseId)));
+        }
+
+        if goal.weights.is_empty() && goal.minimums.is_empty() {
+            errors.push(error(index, goal, "weights".to_string(), "Give weights or minimums to optimize for".to_string()));
+        }
+        for (field, stats) in [("weights", &goal.weights), ("minimums", &goal.minimums)] {
+            for stat in stats.keys().filter(|stat| stat_index(stat).is_none()) {
+                errors.push(error(
+                    index,
+                    goal,
+                    format!("{}.{}", field, stat),
+                    format!("Unknown stat {}, expected one of {}", stat, STAT_NAMES.join(", "))
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Assigns mods to each goal in turn. For every character it tries no set, each set on its own, a 4 piece
+/// set with a 2 piece set and up to three 2 piece sets, trying the best CANDIDATES mods left in each slot.
+/// It's a heuristic so a hand picked loadout can sometimes do better
+pub fn optimize(goals: &[OptimizeGoal], bases: &[StatValues], mods: &[ModView]) -> OptimizeResult {
+    let layouts = set_layouts();
+    let mut taken: HashSet<&str> = HashSet::new();
+    let mut characters = Vec::new();
+    let mut moves = Vec::new();
+
+    for (goal, base) in goals.iter().zip(bases) {
+        let available: Vec<&ModView> = mods.iter().filter(|statMod| !taken.contains(statMod.id.as_str())).collect();
+        let chosen = best_loadout(goal, base, &available, &layouts);
+
+        let equipped: Vec<&ModView> = mods.iter().filter(|statMod| statMod.baseId == goal.baseId).collect();
+        let after = calculate(base, &chosen);
+        let unmet = goal.minimums
+            .iter()
+            .filter(|(stat, minimum)| stat_index(stat).is_some_and(|index| after[index] < **minimum))
+            .map(|(stat, _)| stat.clone())
+            .collect();
+
+        for statMod in &chosen {
+            taken.insert(&statMod.id);
+            if statMod.baseId != goal.baseId {
+                moves.push(ModMove {
+                    modId: statMod.id.clone(),
+                    set: statMod.set,
+                    slot: statMod.slot,
+                    from: statMod.baseId.clone(),
+                    to: goal.baseId.clone()
+                });
+            }
+        }
+
+        characters.push(Loadout {
+            baseId: goal.baseId.clone(),
+            mods: chosen.iter().map(|statMod| statMod.id.clone()).collect(),
+            before: rounded(&calculate(base, &equipped)),
+            after: rounded(&after),
+            unmet
+        });
+    }
+
+    OptimizeResult { characters, moves }
+}
+
+fn rounded(stats: &StatValues) -> Stats {
+    named(&stats.map(|value| (value * 10000.0).round() / 10000.0))
+}
+
+/// The sets each layout forces and how many pieces of each
+fn set_layouts() -> Vec<Vec<(u32, usize)>> {
+    let fours: Vec<u32> = SET_BONUSES.iter().filter(|set| set.1 == 4).map(|set| set.0).collect();
+    let twos: Vec<u32> = SET_BONUSES.iter().filter(|set| set.1 == 2).map(|set| set.0).collect();
+
+    let mut layouts = vec![vec![]];
+    for &four in &fours {
+        layouts.push(vec![(four, 4)]);
+        for &two in &twos {
+            layouts.push(vec![(four, 4), (two, 2)]);
+        }
+    }
+    for (i, &first) in twos.iter().enumerate() {
+        layouts.push(vec![(first, 2)]);
+        for (j, &second) in twos.iter().enumerate().skip(i + 1) {
+            layouts.push(vec![(first, 2), (second, 2)]);
+            for &third in &twos[j + 1..] {
+                layouts.push(vec![(first, 2), (second, 2), (third, 2)]);
+            }
+        }
+    }
+    layouts
+}
+
+/// Every way to spread a layout's set pieces over the six slots, None leaves the slot free
+fn slot_assignments(layout: &[(u32, usize)]) -> Vec<[Option<u32>; 6]> {
+    fn assign(remaining: &mut Vec<(u32, usize)>, slot: usize, current: &mut [Option<u32>; 6], out: &mut Vec<[Option<u32>; 6]>) {
+        if slot == SLOTS.len() {
+            if remaining.iter().all(|(_, count)| *count == 0) {
+                out.push(*current);
+            }
+            return;
+        }
+
+        let needed: usize = remaining.iter().map(|(_, count)| count).sum();
+        if needed < SLOTS.len() - slot {
+            current[slot] = None;
+            assign(remaining, slot + 1, current, out);
+        }
+        for i in 0..remaining.len() {
+            if remaining[i].1 > 0 {
+                remaining[i].1 -= 1;
+                current[slot] = Some(remaining[i].0);
+                assign(remaining, slot + 1, current, out);
+                remaining[i].1 += 1;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    assign(&mut layout.to_vec(), 0, &mut [None; 6], &mut out);
+    out
+}
+
+/// Goal stats by their place in StatValues, checked against STAT_NAMES by goal_errors
+fn indexed(stats: &BTreeMap<String, f64>) -> Vec<(usize, f64)> {
+    stats.iter().filter_map(|(stat, value)| Some((stat_index(stat)?, *value))).collect()
+}
+
+fn objective(stats: &StatValues, weights: &[(usize, f64)]) -> f64 {
+    weights.iter().map(|(index, weight)| stats[*index] * weight).sum()
+}
+
+/// How far under the minimums the stats are, each as a fraction of its minimum
+fn shortfall(stats: &StatValues, minimums: &[(usize, f64)]) -> f64 {
+    minimums
+        .iter()
+        .filter(|(_, minimum)| *minimum > 0.0)
+        .map(|(index, minimum)| ((minimum - stats[*index]) / minimum).max(0.0))
+        .sum()
+}
+
+fn best_loadout<'a>(goal: &OptimizeGoal, base: &StatValues, available: &[&'a ModView], layouts: &[Vec<(u32, usize)>]) -> Vec<&'a ModView> {
+    let weights = indexed(&goal.weights);
+    let minimums = indexed(&goal.minimums);
+
+    // Mods are ranked on what they add on their own by the weights, with reaching a minimum worth as much as a weight of 1
+    let mut rankWeights = weights.clone();
+    for (index, minimum) in &minimums {
+        if *minimum > 0.0 {
+            rankWeights.push((*index, 1.0 / minimum));
+        }
+    }
+    let mut ranked: Vec<(f64, &ModView, StatValues)> = available
+        .iter()
+        .map(|statMod| {
+            let added = mod_stats(base, statMod);
+            (objective(&added, &rankWeights), *statMod, added)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let candidates = |slot: u32, setId: Option<u32>| -> Vec<(&'a ModView, StatValues)> {
+        ranked
+            .iter()
+            .filter(|(_, statMod, _)| statMod.slotId == slot && setId.is_none_or(|setId| statMod.setId == setId))
+            .take(CANDIDATES)
+            .map(|(_, statMod, added)| (*statMod, *added))
+            .collect()
+    };
+
+    let mut best: Option<(f64, f64, Vec<&'a ModView>)> = None;
+    let mut chosen: Vec<&ModView> = Vec::with_capacity(SLOTS.len());
+    for layout in layouts {
+        for assignment in slot_assignments(layout) {
+            let options: Vec<Vec<(&ModView, StatValues)>> = SLOTS.iter().zip(assignment).map(|(slot, setId)| candidates(*slot, setId)).collect();
+            // A forced set needs a mod of that set in the slot, a free slot can stay empty
+            if options.iter().zip(assignment).any(|(mods, setId)| mods.is_empty() && setId.is_some()) {
+                continue;
+            }
+
+            let mut picks = vec![0usize; SLOTS.len()];
+            loop {
+                // Each mod's own stats were worked out while ranking, only the set bonuses depend on the rest
+                chosen.clear();
+                let mut stats = *base;
+                for (statMod, added) in options.iter().zip(&picks).filter_map(|(mods, pick)| mods.get(*pick)) {
+                    chosen.push(statMod);
+                    for (stat, value) in stats.iter_mut().zip(added) {
+                        *stat += value;
+                    }
+                }
+                for (stat, value) in stats.iter_mut().zip(set_stats(base, &chosen)) {
+                    *stat += value;
+                }
+
+                let missing = shortfall(&stats, &minimums);
+                let score = objective(&stats, &weights);
+                let better = match &best {
+                    None => true,
+                    Some((bestMissing, bestScore, _)) => missing < *bestMissing || (missing == *bestMissing && score > *bestScore),
+                };
+                if better {
+                    best = Some((missing, score, chosen.clone()));
+                }
+
+                // Next combination, like counting with each slot's options as a digit
+                let mut slot = 0;
+                while slot < picks.len() {
+                    picks[slot] += 1;
+                    if picks[slot] < options[slot].len() {
+                        break;
+                    }
+                    picks[slot] = 0;
+                    slot += 1;
+                }
+                if slot == picks.len() {
+                    break;
+                }
+            }
+        }
+    }
+
+    best.map(|(_, _, chosen)| chosen).unwrap_or_default()
+}
+
+//curl -X POST http://localhost:7474/mods/optimize -H "Authorization: Bearer <token>" -H "Content-Type: application/json" -d '{"characters": [{"baseId": "GENERALKENOBI", "weights": {"speed": 1, "health": 0.001}, "minimums": {"potency": 0.5}}]}'
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap, net::SocketAddr, sync::{Arc, Mutex}, time::{Duration, Instant}
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{DecodingKey, Validation, decode};
+
+use crate::auth::Claims;
+use crate::error::ApiError;
+
+/// Once this many callers are tracked, finished windows are dropped
+const PRUNE_AT: usize = 10_000;
+
+struct Window {
+    start: Instant,
+    count: u32,
+}
+
+/// Fixed window limiter with a bucket per user from a valid token and one per client IP. A request counts
+/// against both, so one user can't spread over many IPs and one IP can't spread over many users. The IP limit
+/// is higher since users behind the same NAT or proxy share it
+pub struct RateLimiter {
+    limit: u32,
+    ipLimit: u32,
+    window: Duration,
+    jwt_secret: String,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, ipLimit: u32, window: Duration, jwt_secret: String) -> Arc<RateLimiter> {
+        Arc::new(RateLimiter {
+            limit,
+            ipLimit,
+            window,
+            jwt_secret,
+            windows: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Counts a request against every `(key, limit)` bucket, returning how long to wait when any of them is
+    /// over its limit. Nothing is counted for a refused request
+    fn check(&self, buckets: &[(String, u32)]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() >= PRUNE_AT {
+            windows.retain(|_, w| now.duration_since(w.start) < self.window);
+        }
+
+        let mut wait = Duration::ZERO;
+        for (key, limit) in buckets {
+            let window = windows.entry(key.clone()).or_insert(Window { start: now, count: 0 });
+            if now.duration_since(window.start) >= self.window {
+                window.start = now;
+                window.count = 0;
+            }
+            if window.count >= *limit {
+                wait = wait.max(self.window - now.duration_since(window.start));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for (key, _) in buckets {
+            if let Some(window) = windows.get_mut(key) {
+                window.count += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// The user id from a token we signed, so a forged token can't dodge the limit
+    fn user_key(&self, request: &Request) -> Option<String> {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?
+            .trim();
+
+        decode::<Claims>(token, &DecodingKey::from_secret(self.jwt_secret.as_ref()), &Validation::default())
+            .ok()
+            .map(|decoded| format!("user:{}", decoded.claims.sub))
+    }
+}
+
+/// Middleware for routes that call comlink, add it with `middleware::from_fn_with_state(limiter, rate_limit)`
+pub async fn rate_limit(State(limiter): State<Arc<RateLimiter>>, request: Request, next: Next) -> Response {
+    let ip = match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    };
+
+    let mut buckets = vec![(ip, limiter.ipLimit)];
+    if let Some(user) = limiter.user_key(&request) {
+        buckets.push((user, limiter.limit));
+    }
+
+    if let Err(wait) = limiter.check(&buckets) {
+        let keys: Vec<&str> = buckets.iter().map(|(key, _)| key.as_str()).collect();
+        println!("rate limited {}", keys.join(", "));
+        return ApiError::TooManyRequests {
+            message: "Too many requests, slow down".to_string(),
+            // Round up so clients never retry a moment too early
+            retryAfter: wait.as_secs() + 1,
+        }
+        .into_response();
+    }
+
+    next.run(request).await
+}
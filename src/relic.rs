@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use serde::{Serialize, Deserialize};
+use sqlx::{SqlitePool, prelude::FromRow};
+
+use crate::auth::VerifiedUser;
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::plan::{check_plan_owner, goal_errors, load_ships, unknown_unit_errors};
+use crate::types::{CREDITS_ID, GameData, relic_level};
+
+#[derive(Deserialize, Serialize)]
+pub struct RelicPayload {
+    planId: Option<i64>,
+    characters: Option<Vec<RelicGoal>>
+}
+
+#[derive(Deserialize, Serialize, FromRow)]
+pub struct RelicGoal {
+    baseId: String,
+    goalRelic: i64
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MaterialCount {
+    pub materialId: String,
+    pub quantity: u32,
+    pub owned: Option<u32>,
+    pub missing: Option<u32>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CharRelicsNeeded {
+    pub baseId: String,
+    pub currentRelic: u32,
+    pub goalRelic: u32,
+    pub materials: Vec<MaterialCount>,
+    pub credits: u64
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RelicsNeeded {
+    pub characters: Vec<CharRelicsNeeded>,
+    pub materials: Vec<MaterialCount>,
+    pub credits: u64,
+    pub hasInventory: bool
+}
+
+/// Relic materials and credits still needed to reach relic goals, for a saved plan (`planId`)
+/// or an ad-hoc list of `characters`. When the caller's inventory has been stored the totals
+/// are compared against it
+pub async fn relics_needed(
+    State(pool): State<SqlitePool>,
+    VerifiedUser(user): VerifiedUser,
+    Json(payload): Json<RelicPayload>
+) -> Result<Json<RelicsNeeded>, ApiError> {
+    let allyCode = &user.allyCode;
+
+    let goals: Vec<RelicGoal> = match (payload.planId, payload.characters) {
+        (Some(planId), _) => {
+            check_plan_owner(&pool, planId, allyCode).await?;
+            sqlx::query_as(r#"SELECT baseId, goalRelic FROM charPlan WHERE planId = ? ORDER BY priority, id"#)
+                .bind(planId)
+                .fetch_all(&pool)
+                .await?
+        }
+        (None, Some(characters)) => characters,
+        (None, None) => return Err(ApiError::BadRequest("planId or characters required".to_string()))
+    };
+
+    let ships = load_ships(&pool).await?;
+    let mut errors = match payload.planId {
+        Some(_) => Vec::new(),
+        None => {
+            let baseIds: Vec<&str> = goals.iter().map(|goal| goal.baseId.as_str()).collect();
+            unknown_unit_errors(&pool, &baseIds).await?
+        }
+    };
+    errors.extend(goals.iter()
+        .enumerate()
+        .flat_map(|(index, goal)| goal_errors(index, &goal.baseId, &ships, None, None, Some(goal.goalRelic))));
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let inventoryRows: Vec<(String, i64)> = sqlx::query_as(r#"SELECT itemId, quantity FROM inventory WHERE allyCode = ?"#)
+        .bind(allyCode)
+        .fetch_all(&pool)
+        .await?;
+    let hasInventory = !inventoryRows.is_empty();
+    let inventory: BTreeMap<String, u32> = inventoryRows.into_iter()
+        .map(|(id, quantity)| (id, quantity as u32))
+        .collect();
+
+    let to_counts = |counts: BTreeMap<String, u32>| -> Vec<MaterialCount> {
+        counts.into_iter()
+            .map(|(materialId, quantity)| {
+                let owned = hasInventory.then(|| inventory.get(&materialId).copied().unwrap_or(0));
+                MaterialCount {
+                    missing: owned.map(|owned| quantity.saturating_sub(owned)),
+                    materialId,
+                    quantity,
+                    owned
+                }
+            })
+            .collect()
+    };
+
+    let mut characters: Vec<CharRelicsNeeded> = Vec::new();
+    let mut totalMaterials: BTreeMap<String, u32> = BTreeMap::new();
+    let mut totalCredits: u64 = 0;
+
+    for goal in goals {
+        let currentRelic = sqlx::query_scalar::<_, Option<i64>>(r#"SELECT relicTier FROM rosterUnit WHERE allyCode = ? AND definitionId = ?"#)
+            .bind(allyCode)
+            .bind(&goal.baseId)
+            .fetch_optional(&pool)
+            .await?
+            .flatten()
+            .and_then(relic_level)
+            .unwrap_or(0);
+
+        // relicTier rows are keyed by the level they upgrade to, so R3 -> R5 needs the recipes for 4 and 5
+        let ingredients: Vec<(String, i64)> = sqlx::query_as(r#"
+            SELECT recipeIngredient.ingredientId, recipeIngredient.quantity
+            FROM relicTier
+                INNER JOIN relicTierDefinition ON relicTierDefinition.relicTierDefinitionId = relicTier.relicTierDefinitionId
+                INNER JOIN recipeIngredient ON recipeIngredient.recipeId = relicTierDefinition.recipeId
+            WHERE relicTier.baseId = ? AND relicTier.relicTier > ? AND relicTier.relicTier <= ?
+        "#)
+        .bind(&goal.baseId)
+        .bind(currentRelic as i64)
+        .bind(goal.goalRelic)
+        .fetch_all(&pool)
+        .await?;
+
+        let mut materials: BTreeMap<String, u32> = BTreeMap::new();
+        let mut credits: u64 = 0;
+        for (ingredientId, quantity) in ingredients {
+            if ingredientId == CREDITS_ID {
+                credits += quantity as u64;
+            } else {
+                *materials.entry(ingredientId.clone()).or_default() += quantity as u32;
+                *totalMaterials.entry(ingredientId).or_default() += quantity as u32;
+            }
+        }
+        totalCredits += credits;
+
+        characters.push(CharRelicsNeeded {
+            baseId: goal.baseId,
+            currentRelic,
+            goalRelic: goal.goalRelic as u32,
+            materials: to_counts(materials),
+            credits
+        });
+    }
+
+    Ok(Json(RelicsNeeded {
+        characters,
+        materials: to_counts(totalMaterials),
+        credits: totalCredits,
+        hasInventory
+    }))
+}
+
+/// Replaces the stored relic tier definitions and which tier each unit uses at every relic level
+pub async fn setRelicsToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    println!("setting {} relic tiers", gamedata.relicTierDefinition.len());
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(r#"DELETE FROM relicTierDefinition"#).execute(&mut *tx).await?;
+    sqlx::query(r#"DELETE FROM relicTier"#).execute(&mut *tx).await?;
+
+    for definition in &gamedata.relicTierDefinition {
+        sqlx::query(r#"INSERT OR REPLACE INTO relicTierDefinition (relicTierDefinitionId, recipeId) VALUES (?, ?)"#)
+            .bind(&definition.id)
+            .bind(&definition.recipeId)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    for unit in &gamedata.units {
+        let Some(relicDefinition) = &unit.relicDefinition else {
+            continue;
+        };
+
+        // The unit lists its relic tiers in order, the first one upgrades R0 to R1
+        for (index, relicTierDefinitionId) in relicDefinition.relicTierDefinitionId.iter().enumerate() {
+            sqlx::query(r#"
+                INSERT OR REPLACE INTO relicTier (baseId, relicTier, relicTierDefinitionId)
+                VALUES (?, ?, ?)
+            "#)
+            .bind(&unit.baseId)
+            .bind(index as i64 + 1)
+            .bind(relicTierDefinitionId)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    println!("relic tiers added");
+
+    Ok(())
+}
+
+// curl -X POST http://localhost:7474/relics_needed \
+//  -H "Content-Type: application/json" \
+//  -H "Authorization: Bearer <token>" \
+//  -d '{"characters": [{"baseId": "GRANDMASTERYODA", "goalRelic": 8}]}'
@@ -0,0 +1,95 @@
+use axum::{extract::{FromRef, FromRequestParts}, http::request::Parts};
+use serde::Serialize;
+
+use crate::auth::{AuthBearer, AuthUser};
+use crate::error::ApiError;
+use crate::state::AppState;
+
+/// What a user may do. Guild roles come from the guild's member list in game, admin is set on the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Member,
+    Officer,
+    Leader,
+    Admin
+}
+
+impl Role {
+    /// comlink's memberLevel, 2 is a member, 3 an officer and 4 the leader
+    pub fn from_member_level(memberLevel: u32) -> Option<Role> {
+        match memberLevel {
+            2 => Some(Role::Member),
+            3 => Some(Role::Officer),
+            4 => Some(Role::Leader),
+            _ => None
+        }
+    }
+}
+
+/// Admins are flagged on the user, or listed by username in ADMIN_USERNAMES so the first one can be made
+pub async fn is_admin(state: &AppState, userId: i64) -> Result<bool, ApiError> {
+    let (username, isAdmin): (String, bool) = sqlx::query_as(r#"SELECT username, isAdmin FROM user WHERE id = ?"#)
+        .bind(userId)
+        .fetch_one(&state.pool)
+        .await?;
+
+    Ok(isAdmin || state.config.admin_usernames.contains(&username))
+}
+
+/// The guild the ally code is in and its playerId, from the account's last sync
+pub async fn stored_guild(state: &AppState, allyCode: &str) -> Result<(String, String), ApiError> {
+    let (guildId, playerId): (String, String) = sqlx::query_as(r#"SELECT guildId, playerId FROM account WHERE allyCode = ?"#)
+        .bind(allyCode)
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Ally code {} hasn't been synced yet", allyCode)))?;
+
+    if guildId.is_empty() {
+        return Err(ApiError::NotFound("Player is not in a guild".to_string()));
+    }
+    Ok((guildId, playerId))
+}
+
+/// The guild the request's ally code is in and its role there, from the member list stored by the last
+/// `/guild/refresh`. Never asks comlink, the refresh is what pulls the guild
+pub async fn guild_role(state: &AppState, allyCode: &str) -> Result<(String, Role), ApiError> {
+    let (guildId, _) = stored_guild(state, allyCode).await?;
+
+    let memberLevel: i64 = sqlx::query_scalar(r#"SELECT memberLevel FROM guildMember WHERE guildId = ? AND allyCode = ?"#)
+        .bind(&guildId)
+        .bind(allyCode)
+        .fetch_optional(&state.pool)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Not in the guild's member list yet, an officer can sync it with /guild/refresh".to_string()))?;
+
+    let role = Role::from_member_level(memberLevel as u32)
+        .ok_or_else(|| ApiError::NotFound("Player is not in a guild".to_string()))?;
+
+    Ok((guildId, role))
+}
+
+/// A site admin, see `is_admin`. Admin is about the user so the ally code doesn't need to be verified
+pub struct Admin(pub AuthUser);
+
+impl<S> FromRequestParts<S> for Admin
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthBearer(user) = AuthBearer::from_request_parts(parts, state).await?;
+        let state = AppState::from_ref(state);
+
+        if !is_admin(&state, user.userId).await? {
+            return Err(ApiError::Forbidden("Only admins can do this".to_string()));
+        }
+
+        Ok(Admin(user))
+    }
+}
@@ -1,8 +1,12 @@
-use sqlx::{Sqlite, Pool};
+use std::collections::HashMap;
+
+use sqlx::{Sqlite, Pool, SqliteConnection};
 use chrono::Utc;
 use crate::types::{Player};
 
-pub async fn setRosterDatabase(player: &Player, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+/// Writes the player's account, roster, mods and inventory. Run it in the same transaction as clearing
+/// the old roster so a failed sync leaves the previous one in place
+pub async fn setRosterDatabase(player: &Player, conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
     println!("setting roster database");
     sqlx::query(
         r#"
@@ -28,7 +32,7 @@ pub async fn setRosterDatabase(player: &Player, pool: &Pool<Sqlite>) -> Result<(
     .bind(&player.playerRating.playerRankStatus.leagueId)
     .bind(player.playerRating.playerRankStatus.divisionId as i64)
     .bind(Utc::now().to_rfc3339())
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
 
     println!("setting units");
@@ -47,8 +51,100 @@ pub async fn setRosterDatabase(player: &Player, pool: &Pool<Sqlite>) -> Result<(
         .bind(unit.currentTier)
         .bind(unit.relic.as_ref().map(|r| r.currentTier as i64))
         .bind(&player.allyCode)
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
+
+        for skill in &unit.skill {
+            sqlx::query(r#"INSERT OR REPLACE INTO rosterSkill (allyCode, baseId, skillId, tier) VALUES (?, ?, ?, ?)"#)
+                .bind(&player.allyCode)
+                .bind(baseId[0])
+                .bind(&skill.id)
+                .bind(skill.tier)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        for equipped in &unit.equipment {
+            sqlx::query(r#"INSERT OR REPLACE INTO rosterEquipment (allyCode, baseId, slot, equipmentId) VALUES (?, ?, ?, ?)"#)
+                .bind(&player.allyCode)
+                .bind(baseId[0])
+                .bind(equipped.slot)
+                .bind(&equipped.equipmentId)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        for abilityId in &unit.purchasedAbilityId {
+            sqlx::query(r#"INSERT OR REPLACE INTO rosterPurchasedAbility (allyCode, baseId, abilityId) VALUES (?, ?, ?)"#)
+                .bind(&player.allyCode)
+                .bind(baseId[0])
+                .bind(abilityId)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        for statMod in &unit.equippedStatMod {
+            let Some((setId, rarity, slot)) = statMod.set_rarity_slot() else {
+                eprintln!("skipping mod {} with definition {}", statMod.id, statMod.definitionId);
+                continue;
+            };
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO mod (
+                    id, allyCode, baseId, definitionId, setId, rarity, slot, level, tier, primaryStatId, primaryValue
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(&statMod.id)
+            .bind(&player.allyCode)
+            .bind(baseId[0])
+            .bind(&statMod.definitionId)
+            .bind(setId)
+            .bind(rarity)
+            .bind(slot)
+            .bind(statMod.level)
+            .bind(statMod.tier)
+            .bind(statMod.primaryStat.stat.unitStatId)
+            .bind(statMod.primaryStat.stat.raw_value())
+            .execute(&mut *conn)
+            .await?;
+
+            for secondary in &statMod.secondaryStat {
+                sqlx::query(r#"INSERT OR REPLACE INTO modStat (modId, statId, value, rolls) VALUES (?, ?, ?, ?)"#)
+                    .bind(&statMod.id)
+                    .bind(secondary.stat.unitStatId)
+                    .bind(secondary.stat.raw_value())
+                    .bind(secondary.statRolls)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+    }
+
+    // comlink only sends the inventory for some requests, keep the last one we saw otherwise
+    if let Some(inventory) = &player.inventory {
+        println!("setting inventory");
+        sqlx::query(r#"DELETE FROM inventory WHERE allyCode = ?"#)
+            .bind(&player.allyCode)
+            .execute(&mut *conn)
+            .await?;
+
+        let items = inventory.material.iter().map(|item| ("material", item))
+            .chain(inventory.equipment.iter().map(|item| ("equipment", item)));
+        for (itemType, item) in items {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO inventory (allyCode, itemId, itemType, quantity)
+                VALUES (?, ?, ?, ?)
+                "#
+            )
+            .bind(&player.allyCode)
+            .bind(&item.id)
+            .bind(itemType)
+            .bind(item.quantity)
+            .execute(&mut *conn)
+            .await?;
+        }
     }
     println!("set into database");
     println!("{}", Utc::now());
@@ -56,6 +152,28 @@ pub async fn setRosterDatabase(player: &Player, pool: &Pool<Sqlite>) -> Result<(
     Ok(())
 }
 
+/// Fills in hasZeta and hasOmicron on every skill from the stored skill definitions
+pub async fn mark_skill_upgrades(player: &mut Player, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let rows: Vec<(String, Option<i64>, Option<i64>)> = sqlx::query_as(
+        r#"SELECT skillId, zetaTier, omicronTier FROM skill WHERE zetaTier IS NOT NULL OR omicronTier IS NOT NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+    let upgrades: HashMap<String, (Option<i64>, Option<i64>)> = rows
+        .into_iter()
+        .map(|(skillId, zetaTier, omicronTier)| (skillId, (zetaTier, omicronTier)))
+        .collect();
+
+    for skill in player.rosterUnit.iter_mut().flat_map(|unit| unit.skill.iter_mut()) {
+        if let Some((zetaTier, omicronTier)) = upgrades.get(&skill.id) {
+            skill.hasZeta = zetaTier.is_some_and(|tier| skill.tier >= tier);
+            skill.hasOmicron = omicronTier.is_some_and(|tier| skill.tier >= tier);
+        }
+    }
+
+    Ok(())
+}
+
 
 use crate::types::*;
 
@@ -76,20 +194,84 @@ pub async fn get_player_from_db(ally_code: &str, pool: &sqlx::Pool<sqlx::Sqlite>
     .fetch_all(pool)
     .await?;
 
+    let skillRows: Vec<(String, String, i64)> = sqlx::query_as(
+        r#"SELECT baseId, skillId, tier FROM rosterSkill WHERE allyCode = ? ORDER BY skillId"#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+    let mut skills: HashMap<String, Vec<UnitSkill>> = HashMap::new();
+    for (baseId, id, tier) in skillRows {
+        skills.entry(baseId).or_default().push(UnitSkill { id, tier, hasZeta: false, hasOmicron: false });
+    }
+
+    let equipmentRows: Vec<(String, String, i64)> = sqlx::query_as(
+        r#"SELECT baseId, equipmentId, slot FROM rosterEquipment WHERE allyCode = ? ORDER BY slot"#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+    let mut equipment: HashMap<String, Vec<EquippedGear>> = HashMap::new();
+    for (baseId, equipmentId, slot) in equipmentRows {
+        equipment.entry(baseId).or_default().push(EquippedGear { equipmentId, slot: slot as u32 });
+    }
+
+    let abilityRows: Vec<(String, String)> = sqlx::query_as(
+        r#"SELECT baseId, abilityId FROM rosterPurchasedAbility WHERE allyCode = ? ORDER BY abilityId"#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+    let mut purchasedAbilities: HashMap<String, Vec<String>> = HashMap::new();
+    for (baseId, abilityId) in abilityRows {
+        purchasedAbilities.entry(baseId).or_default().push(abilityId);
+    }
+
+    let mods = get_mods_from_db(ally_code, pool).await?;
+    let mut equippedStatMod: HashMap<String, Vec<StatMod>> = HashMap::new();
+    for (baseId, statMod) in mods {
+        equippedStatMod.entry(baseId).or_default().push(statMod);
+    }
+
+    let inventoryRows: Vec<(String, String, i64)> = sqlx::query_as(
+        r#"SELECT itemId, itemType, quantity FROM inventory WHERE allyCode = ?"#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+
+    let inventory = if inventoryRows.is_empty() {
+        None
+    } else {
+        let mut inventory = Inventory { material: Vec::new(), equipment: Vec::new() };
+        for (id, itemType, quantity) in inventoryRows {
+            let item = InventoryItem { id, quantity: quantity as u32 };
+            match itemType.as_str() {
+                "equipment" => inventory.equipment.push(item),
+                _ => inventory.material.push(item),
+            }
+        }
+        Some(inventory)
+    };
+
     // Convert roster rows into your game structs
     let roster_units: Vec<RosterUnit> = roster
         .into_iter()
         .map(|row| RosterUnit {
-            definitionId: row.definitionId,
             currentRarity: row.currentRarity as u32,
             currentLevel: row.currentLevel as u32,
             currentTier: row.currentTier as u32,
             relic: row.relicTier.map(|t| Relic { currentTier: t as u32 }),
+            skill: skills.remove(&row.definitionId).unwrap_or_default(),
+            equipment: equipment.remove(&row.definitionId).unwrap_or_default(),
+            equippedStatMod: equippedStatMod.remove(&row.definitionId).unwrap_or_default(),
+            purchasedAbilityId: purchasedAbilities.remove(&row.definitionId).unwrap_or_default(),
+            definitionId: row.definitionId,
         })
         .collect();
 
     // Build Player
-    let player = Player {
+    let mut player = Player {
         rosterUnit: roster_units,
         name: account.name,
         level: account.level as u32,
@@ -106,6 +288,8 @@ pub async fn get_player_from_db(ally_code: &str, pool: &sqlx::Pool<sqlx::Sqlite>
         selectedPlayerPortrait: SelectedPlayerThing {
             id: account.selectedPlayerPortraitId,
         },
+        unlockedPlayerTitle: vec![],
+        unlockedPlayerPortrait: vec![],
         playerRating: PlayerRating {
             playerSkillRating: PlayerSkillRating {
                 skillRating: account.skillRating as u32,
@@ -115,10 +299,12 @@ pub async fn get_player_from_db(ally_code: &str, pool: &sqlx::Pool<sqlx::Sqlite>
                 divisionId: account.divisionId as u32,
             },
         },
+        inventory,
         last_updated: account.last_updated
 
     };
 
+    mark_skill_upgrades(&mut player, pool).await?;
     println!("{}", player.name);
 
     Ok(player)
@@ -146,6 +332,64 @@ struct AccountRow {
     last_updated: String,
 }
 
+/// Every mod the ally code has equipped with the baseId of the unit wearing it
+async fn get_mods_from_db(ally_code: &str, pool: &sqlx::Pool<sqlx::Sqlite>) -> Result<Vec<(String, StatMod)>, sqlx::Error> {
+    let mods = sqlx::query_as::<_, ModRow>(
+        r#"SELECT id, baseId, definitionId, level, tier, primaryStatId, primaryValue FROM mod WHERE allyCode = ? ORDER BY slot"#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+
+    let statRows: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+        r#"
+        SELECT modStat.modId, modStat.statId, modStat.value, modStat.rolls
+        FROM modStat
+            JOIN mod ON mod.id = modStat.modId
+        WHERE mod.allyCode = ?
+        "#,
+    )
+    .bind(ally_code)
+    .fetch_all(pool)
+    .await?;
+    let mut secondaries: HashMap<String, Vec<ModStat>> = HashMap::new();
+    for (modId, statId, value, rolls) in statRows {
+        secondaries.entry(modId).or_default().push(ModStat {
+            stat: UnitStat { unitStatId: statId as u32, statValueDecimal: value.to_string() },
+            statRolls: rolls as u32,
+        });
+    }
+
+    Ok(mods
+        .into_iter()
+        .map(|row| {
+            let statMod = StatMod {
+                secondaryStat: secondaries.remove(&row.id).unwrap_or_default(),
+                id: row.id,
+                definitionId: row.definitionId,
+                level: row.level as u32,
+                tier: row.tier as u32,
+                primaryStat: ModStat {
+                    stat: UnitStat { unitStatId: row.primaryStatId as u32, statValueDecimal: row.primaryValue.to_string() },
+                    statRolls: 0,
+                },
+            };
+            (row.baseId, statMod)
+        })
+        .collect())
+}
+
+#[derive(FromRow)]
+struct ModRow {
+    id: String,
+    baseId: String,
+    definitionId: String,
+    level: i64,
+    tier: i64,
+    primaryStatId: i64,
+    primaryValue: i64,
+}
+
 #[derive(FromRow)]
 struct RosterUnitRow {
     definitionId: String,
@@ -1,80 +1,550 @@
-use sqlx::{SqlitePool};
+use sqlx::{SqliteConnection, SqlitePool};
 
-pub async fn dbSetup() {
-    let pool = SqlitePool::connect("sqlite:////data/mydb.sqlite").await.unwrap();
+/// One change to the schema, a migration runs its steps in order
+enum Step {
+    Sql(&'static str),
+    /// SQLite has no ADD COLUMN IF NOT EXISTS, this checks the table first
+    AddColumn { table: &'static str, column: &'static str, definition: &'static str },
+}
 
+struct Migration {
+    version: i64,
+    name: &'static str,
+    steps: &'static [Step],
+}
+
+/// Every schema change ever made, in order. Never edit a migration that has shipped,
+/// add a new one at the end instead
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        steps: &[
+            Step::Sql(BASELINE),
+            // Tables made before these columns existed don't get them from CREATE TABLE IF NOT EXISTS
+            Step::AddColumn { table: "plan", column: "priority", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::AddColumn { table: "charPlan", column: "priority", definition: "INTEGER NOT NULL DEFAULT 0" },
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "account, rosterUnit and user",
+        steps: &[Step::Sql(ACCOUNTS)],
+    },
+    Migration {
+        version: 3,
+        name: "refresh tokens and revocation",
+        steps: &[Step::Sql(TOKENS)],
+    },
+    Migration {
+        version: 4,
+        name: "ally code verification",
+        steps: &[
+            // Accounts made before verification existed start unverified like everyone else
+            Step::AddColumn { table: "user", column: "verified", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::Sql(VERIFICATION),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "linked ally codes",
+        steps: &[Step::Sql(LINKED_ACCOUNTS)],
+    },
+    Migration {
+        version: 6,
+        name: "password resets",
+        steps: &[Step::Sql(PASSWORD_RESETS)],
+    },
+    Migration {
+        version: 7,
+        name: "admin role",
+        steps: &[
+            Step::AddColumn { table: "user", column: "isAdmin", definition: "INTEGER NOT NULL DEFAULT 0" },
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "roster snapshots",
+        steps: &[Step::Sql(ROSTER_SNAPSHOTS)],
+    },
+    Migration {
+        version: 9,
+        name: "skills and equipped gear",
+        steps: &[
+            Step::AddColumn { table: "skill", column: "zetaTier", definition: "INTEGER" },
+            Step::AddColumn { table: "skill", column: "omicronTier", definition: "INTEGER" },
+            Step::AddColumn { table: "skill", column: "omicronMode", definition: "INTEGER NOT NULL DEFAULT 0" },
+            Step::Sql(ROSTER_SKILLS),
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "mods",
+        steps: &[Step::Sql(MODS)],
+    },
+    // 11 and 12 were withdrawn before release
+    Migration {
+        version: 13,
+        name: "purchased abilities",
+        steps: &[Step::Sql(ROSTER_PURCHASED_ABILITIES)],
+    },
+    Migration {
+        version: 14,
+        name: "unit combat type",
+        steps: &[
+            Step::AddColumn { table: "unit", column: "combatType", definition: "INTEGER NOT NULL DEFAULT 1" },
+        ],
+    },
+    Migration {
+        version: 15,
+        name: "star promotion costs",
+        steps: &[Step::Sql(STAR_PROMOTION)],
+    },
+    Migration {
+        version: 16,
+        name: "unit base stats",
+        steps: &[Step::Sql(UNIT_BASE_STATS)],
+    },
+];
+
+/// Brings the database up to the latest schema, running each migration it hasn't seen yet in its own transaction
+pub async fn dbSetup(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
-        PRAGMA foreign_keys = ON;
         PRAGMA journal_mode = WAL;
         PRAGMA synchronous = NORMAL;
 
-        CREATE TABLE IF NOT EXISTS unit (
-            baseId TEXT PRIMARY KEY,
-            iconPath TEXT NOT NULL,
-            thumbnailName TEXT NOT NULL,
-            relicDefinition TEXT
+        CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            appliedAt TEXT NOT NULL
         );
+        "#
+    ).execute(pool).await?;
 
-        CREATE TABLE IF NOT EXISTS category (
-            category_name TEXT PRIMARY KEY NOT NULL
-        );
+    let current: i64 = sqlx::query_scalar(r#"SELECT COALESCE(MAX(version), 0) FROM schema_version"#)
+        .fetch_one(pool)
+        .await?;
 
-        CREATE TABLE IF NOT EXISTS unit_has_trait (
-            baseId TEXT NOT NULL,
-            category_name TEXT NOT NULL,
-            FOREIGN KEY (baseId) REFERENCES unit(baseId),
-            FOREIGN KEY (category_name) REFERENCES category(category_name),
-            PRIMARY KEY (baseId, category_name)
-        );
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        println!("running migration {} ({})", migration.version, migration.name);
+        let mut tx = pool.begin().await?;
 
-        CREATE TABLE IF NOT EXISTS crew (
-            unitId TEXT NOT NULL PRIMARY KEY,
-            baseId TEXT NOT NULL,
-            FOREIGN KEY (baseId) REFERENCES unit(baseId)
-        );
+        for step in migration.steps {
+            match step {
+                Step::Sql(sql) => {
+                    sqlx::raw_sql(sql).execute(&mut *tx).await?;
+                }
+                Step::AddColumn { table, column, definition } => {
+                    add_column_if_missing(&mut tx, table, column, definition).await?;
+                }
+            }
+        }
 
-        CREATE TABLE IF NOT EXISTS skill (
-            skillId TEXT PRIMARY KEY,
-            baseId TEXT NOT NULL,
-            FOREIGN KEY (baseId) REFERENCES unit(baseId)
-        );
+        sqlx::query(r#"INSERT INTO schema_version (version, name, appliedAt) VALUES (?, ?, ?)"#)
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
 
-        CREATE TABLE IF NOT EXISTS unitTier (
-            baseId TEXT NOT NULL,
-            tier INTEGER NOT NULL,
-            FOREIGN KEY (baseId) REFERENCES unit(baseId),
-            PRIMARY KEY (tier, baseId)
-        );
+        tx.commit().await?;
+    }
 
-        CREATE TABLE IF NOT EXISTS equipment (
-            equipmentId TEXT NOT NULL,
-            tier INTEGER NOT NULL,
-            baseId TEXT NOT NULL,
-            PRIMARY KEY (equipmentId, tier, baseId),
-            FOREIGN KEY (tier, baseId) REFERENCES unitTier(tier, baseId)
-        );
+    println!("databases made");
+    Ok(())
+}
 
-        CREATE TABLE IF NOT EXISTS plan (
-            id INTEGER PRIMARY KEY,
-            planName TEXT NOT NULL,
-            icon TEXT NOT NULL,
-            allyCode TEXT NOT NULL,
-            FOREIGN KEY (allyCode) REFERENCES account(allyCode)
-        );
+async fn add_column_if_missing(conn: &mut SqliteConnection, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    let existing: Option<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(&mut *conn)
+        .await?;
 
-        CREATE TABLE IF NOT EXISTS charPlan (
-            id INTEGER PRIMARY KEY,
-            charName TEXT NOT NULL,
-            goalStars INT NOT NULL,
-            goalGear INT NOT NULL,
-            goalRelic INT NOT NULL,
-            baseId TEXT NOT NULL,
-            planId INTEGER NOT NULL,
-            FOREIGN KEY (planId) REFERENCES plan(id)
-        );
-        "#
-    ).execute(&pool).await.unwrap();
+    if existing.is_none() {
+        println!("adding column {} to {}", column, table);
+        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+            .execute(&mut *conn)
+            .await?;
+    }
 
-    println!("databases made");
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Everything created before migrations existed. It uses IF NOT EXISTS so databases that
+/// already have these tables pick up at version 1 without changes
+const BASELINE: &str = r#"
+    CREATE TABLE IF NOT EXISTS unit (
+        baseId TEXT PRIMARY KEY,
+        iconPath TEXT NOT NULL,
+        thumbnailName TEXT NOT NULL,
+        relicDefinition TEXT
+    );
+
+    CREATE TABLE IF NOT EXISTS category (
+        category_name TEXT PRIMARY KEY NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS unit_has_trait (
+        baseId TEXT NOT NULL,
+        category_name TEXT NOT NULL,
+        FOREIGN KEY (baseId) REFERENCES unit(baseId),
+        FOREIGN KEY (category_name) REFERENCES category(category_name),
+        PRIMARY KEY (baseId, category_name)
+    );
+
+    CREATE TABLE IF NOT EXISTS crew (
+        unitId TEXT NOT NULL PRIMARY KEY,
+        baseId TEXT NOT NULL,
+        FOREIGN KEY (baseId) REFERENCES unit(baseId)
+    );
+
+    CREATE TABLE IF NOT EXISTS skill (
+        skillId TEXT PRIMARY KEY,
+        baseId TEXT NOT NULL,
+        FOREIGN KEY (baseId) REFERENCES unit(baseId)
+    );
+
+    CREATE TABLE IF NOT EXISTS unitTier (
+        baseId TEXT NOT NULL,
+        tier INTEGER NOT NULL,
+        FOREIGN KEY (baseId) REFERENCES unit(baseId),
+        PRIMARY KEY (tier, baseId)
+    );
+
+    CREATE TABLE IF NOT EXISTS equipment (
+        equipmentId TEXT NOT NULL,
+        tier INTEGER NOT NULL,
+        baseId TEXT NOT NULL,
+        PRIMARY KEY (equipmentId, tier, baseId),
+        FOREIGN KEY (tier, baseId) REFERENCES unitTier(tier, baseId)
+    );
+
+    CREATE TABLE IF NOT EXISTS equipmentDefinition (
+        equipmentId TEXT PRIMARY KEY,
+        nameKey TEXT NOT NULL,
+        tier INTEGER NOT NULL,
+        mark TEXT NOT NULL,
+        recipeId TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS recipeIngredient (
+        recipeId TEXT NOT NULL,
+        ingredientId TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        PRIMARY KEY (recipeId, ingredientId)
+    );
+
+    CREATE TABLE IF NOT EXISTS material (
+        materialId TEXT PRIMARY KEY,
+        nameKey TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS dropLocation (
+        itemId TEXT NOT NULL,
+        campaignId TEXT NOT NULL,
+        campaignMapId TEXT NOT NULL,
+        campaignNodeDifficulty INTEGER NOT NULL,
+        campaignNodeId TEXT NOT NULL,
+        campaignMissionId TEXT NOT NULL,
+        PRIMARY KEY (itemId, campaignId, campaignMapId, campaignNodeDifficulty, campaignNodeId, campaignMissionId)
+    );
+
+    CREATE TABLE IF NOT EXISTS relicTierDefinition (
+        relicTierDefinitionId TEXT PRIMARY KEY,
+        recipeId TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS relicTier (
+        baseId TEXT NOT NULL,
+        relicTier INTEGER NOT NULL,
+        relicTierDefinitionId TEXT NOT NULL,
+        PRIMARY KEY (baseId, relicTier)
+    );
+
+    CREATE TABLE IF NOT EXISTS inventory (
+        allyCode TEXT NOT NULL,
+        itemId TEXT NOT NULL,
+        itemType TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        PRIMARY KEY (allyCode, itemId)
+    );
+
+    CREATE TABLE IF NOT EXISTS plan (
+        id INTEGER PRIMARY KEY,
+        planName TEXT NOT NULL,
+        icon TEXT NOT NULL,
+        allyCode TEXT NOT NULL,
+        priority INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+
+    CREATE TABLE IF NOT EXISTS charPlan (
+        id INTEGER PRIMARY KEY,
+        charName TEXT NOT NULL,
+        goalStars INT NOT NULL,
+        goalGear INT NOT NULL,
+        goalRelic INT NOT NULL,
+        baseId TEXT NOT NULL,
+        planId INTEGER NOT NULL,
+        priority INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (planId) REFERENCES plan(id)
+    );
+
+    CREATE TABLE IF NOT EXISTS guild (
+        guildId TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        memberCount INTEGER NOT NULL,
+        last_updated TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS guildMember (
+        playerId TEXT PRIMARY KEY,
+        allyCode TEXT NOT NULL,
+        playerName TEXT NOT NULL,
+        memberLevel INTEGER NOT NULL,
+        guildId TEXT NOT NULL,
+        FOREIGN KEY (guildId) REFERENCES guild(guildId)
+    );
+"#;
+
+/// The player, roster and login tables. Older deployments may have made these by hand
+const ACCOUNTS: &str = r#"
+    CREATE TABLE IF NOT EXISTS account (
+        allyCode TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        level INTEGER NOT NULL,
+        playerId TEXT NOT NULL,
+        guildId TEXT NOT NULL,
+        guildName TEXT NOT NULL,
+        guildLogoBackground TEXT NOT NULL,
+        guildBannerColor TEXT NOT NULL,
+        guildBannerLogo TEXT NOT NULL,
+        selectedPlayerTitleId TEXT NOT NULL,
+        selectedPlayerPortraitId TEXT NOT NULL,
+        skillRating INTEGER NOT NULL,
+        leagueId TEXT NOT NULL,
+        divisionId INTEGER NOT NULL,
+        last_updated TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS rosterUnit (
+        definitionId TEXT NOT NULL,
+        currentRarity INTEGER NOT NULL,
+        currentLevel INTEGER NOT NULL,
+        currentTier INTEGER NOT NULL,
+        relicTier INTEGER,
+        allyCode TEXT NOT NULL,
+        PRIMARY KEY (allyCode, definitionId),
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+
+    CREATE TABLE IF NOT EXISTS user (
+        id INTEGER PRIMARY KEY,
+        username TEXT NOT NULL UNIQUE,
+        password TEXT NOT NULL,
+        createdAt TEXT NOT NULL,
+        allyCode TEXT NOT NULL,
+        email TEXT NOT NULL,
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+"#;
+
+/// Refresh tokens are stored as a SHA-256 of the token, every token rotated from one sign-in shares a sessionId
+/// and the session stays signed in while one of them isn't revoked.
+/// revokedToken lists access token ids that were signed out before they expired
+const TOKENS: &str = r#"
+    CREATE TABLE IF NOT EXISTS refreshToken (
+        tokenHash TEXT PRIMARY KEY,
+        userId INTEGER NOT NULL,
+        sessionId TEXT NOT NULL,
+        createdAt TEXT NOT NULL,
+        expiresAt INTEGER NOT NULL,
+        revoked INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (userId) REFERENCES user(id)
+    );
+
+    CREATE INDEX IF NOT EXISTS refreshTokenSession ON refreshToken (sessionId);
+
+    CREATE TABLE IF NOT EXISTS revokedToken (
+        jti TEXT PRIMARY KEY,
+        expiresAt INTEGER NOT NULL
+    );
+"#;
+
+/// The open ally code challenge for each user, see verify.rs. targetId is NULL when any change from previousId passes
+const VERIFICATION: &str = r#"
+    CREATE TABLE IF NOT EXISTS verificationChallenge (
+        userId INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        targetId TEXT,
+        previousId TEXT NOT NULL,
+        expiresAt INTEGER NOT NULL,
+        FOREIGN KEY (userId) REFERENCES user(id)
+    );
+"#;
+
+/// A user can link several ally codes, user.allyCode is now the one used when a request doesn't pick one.
+/// Verification moves from the user to each linked ally code, open challenges are dropped since they only last minutes
+const LINKED_ACCOUNTS: &str = r#"
+    CREATE TABLE IF NOT EXISTS linkedAccount (
+        userId INTEGER NOT NULL,
+        allyCode TEXT NOT NULL,
+        verified INTEGER NOT NULL DEFAULT 0,
+        linkedAt TEXT NOT NULL,
+        PRIMARY KEY (userId, allyCode),
+        FOREIGN KEY (userId) REFERENCES user(id),
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+
+    INSERT OR IGNORE INTO linkedAccount (userId, allyCode, verified, linkedAt)
+        SELECT id, allyCode, verified, createdAt FROM user;
+
+    ALTER TABLE user DROP COLUMN verified;
+
+    DROP TABLE IF EXISTS verificationChallenge;
+    CREATE TABLE verificationChallenge (
+        userId INTEGER NOT NULL,
+        allyCode TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        targetId TEXT,
+        previousId TEXT NOT NULL,
+        expiresAt INTEGER NOT NULL,
+        PRIMARY KEY (userId, allyCode),
+        FOREIGN KEY (userId, allyCode) REFERENCES linkedAccount(userId, allyCode)
+    );
+"#;
+
+/// Single-use password reset tokens, stored as a SHA-256 like refresh tokens
+const PASSWORD_RESETS: &str = r#"
+    CREATE TABLE IF NOT EXISTS passwordReset (
+        tokenHash TEXT PRIMARY KEY,
+        userId INTEGER NOT NULL,
+        createdAt TEXT NOT NULL,
+        expiresAt INTEGER NOT NULL,
+        used INTEGER NOT NULL DEFAULT 0,
+        FOREIGN KEY (userId) REFERENCES user(id)
+    );
+"#;
+
+/// A copy of the roster and rating taken every time an account is pulled from comlink, see history.rs.
+/// relicTier is comlink's raw value like rosterUnit.relicTier
+const ROSTER_SNAPSHOTS: &str = r#"
+    CREATE TABLE IF NOT EXISTS rosterSnapshot (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        allyCode TEXT NOT NULL,
+        takenAt TEXT NOT NULL,
+        skillRating INTEGER NOT NULL,
+        leagueId TEXT NOT NULL,
+        divisionId INTEGER NOT NULL,
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+
+    CREATE INDEX IF NOT EXISTS rosterSnapshotAccount ON rosterSnapshot (allyCode, takenAt);
+
+    CREATE TABLE IF NOT EXISTS snapshotUnit (
+        snapshotId INTEGER NOT NULL,
+        definitionId TEXT NOT NULL,
+        currentRarity INTEGER NOT NULL,
+        currentLevel INTEGER NOT NULL,
+        currentTier INTEGER NOT NULL,
+        relicTier INTEGER,
+        PRIMARY KEY (snapshotId, definitionId),
+        FOREIGN KEY (snapshotId) REFERENCES rosterSnapshot(id)
+    );
+"#;
+
+/// Skill tiers and the gear slotted at the current tier for each roster unit, replaced with rosterUnit on every sync.
+/// skill.zetaTier and omicronTier are compared against rosterSkill.tier
+const ROSTER_SKILLS: &str = r#"
+    CREATE TABLE IF NOT EXISTS rosterSkill (
+        allyCode TEXT NOT NULL,
+        baseId TEXT NOT NULL,
+        skillId TEXT NOT NULL,
+        tier INTEGER NOT NULL,
+        PRIMARY KEY (allyCode, skillId),
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+
+    CREATE TABLE IF NOT EXISTS rosterEquipment (
+        allyCode TEXT NOT NULL,
+        baseId TEXT NOT NULL,
+        slot INTEGER NOT NULL,
+        equipmentId TEXT NOT NULL,
+        PRIMARY KEY (allyCode, baseId, slot),
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+"#;
+
+/// Mods equipped on each roster unit, see mods.rs. setId, rarity and slot are split out of definitionId
+/// and stat values are comlink's statValueDecimal, the value times 10000
+const MODS: &str = r#"
+    CREATE TABLE IF NOT EXISTS mod (
+        id TEXT PRIMARY KEY,
+        allyCode TEXT NOT NULL,
+        baseId TEXT NOT NULL,
+        definitionId TEXT NOT NULL,
+        setId INTEGER NOT NULL,
+        rarity INTEGER NOT NULL,
+        slot INTEGER NOT NULL,
+        level INTEGER NOT NULL,
+        tier INTEGER NOT NULL,
+        primaryStatId INTEGER NOT NULL,
+        primaryValue INTEGER NOT NULL,
+        FOREIGN KEY (allyCode, baseId) REFERENCES rosterUnit(allyCode, definitionId)
+    );
+
+    CREATE INDEX IF NOT EXISTS modAccount ON mod (allyCode, setId, slot);
+
+    CREATE TABLE IF NOT EXISTS modStat (
+        modId TEXT NOT NULL,
+        statId INTEGER NOT NULL,
+        value INTEGER NOT NULL,
+        rolls INTEGER NOT NULL,
+        PRIMARY KEY (modId, statId),
+        FOREIGN KEY (modId) REFERENCES mod(id)
+    );
+"#;
+
+/// Abilities bought for a roster unit, such as ultimates, by comlink's purchasedAbilityId
+const ROSTER_PURCHASED_ABILITIES: &str = r#"
+    CREATE TABLE IF NOT EXISTS rosterPurchasedAbility (
+        allyCode TEXT NOT NULL,
+        baseId TEXT NOT NULL,
+        abilityId TEXT NOT NULL,
+        PRIMARY KEY (allyCode, baseId, abilityId),
+        FOREIGN KEY (allyCode) REFERENCES account(allyCode)
+    );
+"#;
+
+/// The shards and credits to reach each star level. Seeded with the known costs so /shards_needed works
+/// before game data with the promotion tables is loaded, which then replaces them
+const STAR_PROMOTION: &str = r#"
+    CREATE TABLE IF NOT EXISTS starPromotion (
+        stars INTEGER PRIMARY KEY,
+        shards INTEGER NOT NULL,
+        credits INTEGER NOT NULL
+    );
+
+    INSERT OR IGNORE INTO starPromotion (stars, shards, credits) VALUES
+        (1, 10, 0),
+        (2, 15, 10000),
+        (3, 25, 25000),
+        (4, 30, 50000),
+        (5, 65, 100000),
+        (6, 85, 250000),
+        (7, 100, 500000);
+"#;
+
+/// Each unit's stats at every gear tier from game data, used by the stat calculator in stats.rs.
+/// value is statValueDecimal like mod stats
+const UNIT_BASE_STATS: &str = r#"
+    CREATE TABLE IF NOT EXISTS unitBaseStat (
+        baseId TEXT NOT NULL,
+        tier INTEGER NOT NULL,
+        statId INTEGER NOT NULL,
+        value INTEGER NOT NULL,
+        PRIMARY KEY (baseId, tier, statId)
+    );
+"#;
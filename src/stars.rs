@@ -0,0 +1,270 @@
+use axum::extract::State;
+use serde::{Serialize, Deserialize};
+use sqlx::{SqlitePool, prelude::FromRow};
+
+use crate::auth::VerifiedUser;
+use crate::gear::DropLocation;
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::plan::{check_plan_owner, goal_errors, load_ships, unknown_unit_errors};
+use crate::state::AppState;
+use crate::types::GameData;
+
+/// Game data tables with the shards and credits to reach each star level, keyed by the star level.
+/// When game data doesn't have them the costs seeded by the starPromotion migration are used
+const STAR_PROMOTION_SHARDS_TABLE: &str = "unit_rarity_promotion_shards";
+const STAR_PROMOTION_CREDITS_TABLE: &str = "unit_rarity_promotion_credits";
+const MAX_STARS: u32 = 7;
+
+/// Hard nodes cost this much energy per battle
+const ENERGY_PER_BATTLE: u32 = 20;
+/// Hard nodes can be battled this many times a day, each refresh resets it
+const BATTLES_PER_REFRESH: u32 = 5;
+/// Most hard node refreshes a day /shards_needed accepts, crystal costs make anything past this unrealistic
+const MAX_REFRESHES_PER_DAY: u32 = 100;
+
+/// Shards for a unit are a material with this prefix followed by its baseId
+fn shard_id(baseId: &str) -> String {
+    format!("unitshard_{}", baseId)
+}
+
+/// (shards, credits) to reach each star level as stored from the game data, index 0 is unlocking the character at 1 star
+async fn load_star_promotion(pool: &SqlitePool) -> Result<Vec<(u32, u64)>, ApiError> {
+    let rows: Vec<(i64, i64, i64)> = sqlx::query_as(r#"SELECT stars, shards, credits FROM starPromotion ORDER BY stars"#)
+        .fetch_all(pool)
+        .await?;
+
+    let complete = rows.len() == MAX_STARS as usize && rows.iter().enumerate().all(|(index, row)| row.0 == index as i64 + 1);
+    if !complete {
+        return Err(ApiError::Internal("Star promotion costs are missing from the game data".to_string()));
+    }
+    Ok(rows.into_iter().map(|(_, shards, credits)| (shards as u32, credits as u64)).collect())
+}
+
+/// Shards and credits to go from `currentStars` (0 if locked) to `goalStars`
+pub(crate) fn promotion_cost(promotion: &[(u32, u64)], currentStars: u32, goalStars: u32) -> (u32, u64) {
+    promotion.iter()
+        .enumerate()
+        .filter(|(index, _)| (*index as u32) >= currentStars && (*index as u32) < goalStars)
+        .fold((0, 0), |(shards, credits), (_, cost)| (shards + cost.0, credits + cost.1))
+}
+
+/// Days to farm `shards` spread across `locations` hard nodes with the daily energy and refreshes given
+fn farm_days(shards: u32, locations: usize, energyPerDay: u32, refreshesPerDay: u32, shardsPerBattle: f64) -> Option<f64> {
+    if shards == 0 {
+        return Some(0.0);
+    }
+    if locations == 0 {
+        return None;
+    }
+
+    let battleCap = (locations as u32)
+        .saturating_mul(BATTLES_PER_REFRESH)
+        .saturating_mul(refreshesPerDay.saturating_add(1));
+    let battles = battleCap.min(energyPerDay / ENERGY_PER_BATTLE);
+    if battles == 0 {
+        return None;
+    }
+
+    Some((shards as f64 / (battles as f64 * shardsPerBattle)).ceil())
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ShardPayload {
+    planId: Option<i64>,
+    characters: Option<Vec<StarGoal>>,
+    energyPerDay: Option<u32>,
+    refreshesPerDay: Option<u32>
+}
+
+#[derive(Deserialize, Serialize, FromRow)]
+pub struct StarGoal {
+    baseId: String,
+    goalStars: i64
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CharShardsNeeded {
+    pub baseId: String,
+    pub currentStars: u32,
+    pub goalStars: u32,
+    pub shards: u32,
+    pub credits: u64,
+    pub farmLocations: Vec<DropLocation>,
+    pub days: Option<f64>
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ShardsNeeded {
+    pub characters: Vec<CharShardsNeeded>,
+    pub shards: u32,
+    pub credits: u64,
+    pub days: Option<f64>
+}
+
+/// Shards and credits still needed to reach star goals, for a saved plan (`planId`) or an ad-hoc list of
+/// `characters`. When `energyPerDay` is sent, characters with known farm locations get an estimate of the
+/// days needed. The plan's `days` assumes characters are farmed one at a time and is only set when every
+/// character has an estimate, using SHARDS_PER_BATTLE as the drop rate
+pub async fn shards_needed(
+    State(state): State<AppState>,
+    VerifiedUser(user): VerifiedUser,
+    Json(payload): Json<ShardPayload>
+) -> Result<Json<ShardsNeeded>, ApiError> {
+    let pool = &state.pool;
+    let allyCode = &user.allyCode;
+
+    let goals: Vec<StarGoal> = match (payload.planId, payload.characters) {
+        (Some(planId), _) => {
+            check_plan_owner(pool, planId, allyCode).await?;
+            sqlx::query_as(r#"SELECT baseId, goalStars FROM charPlan WHERE planId = ? ORDER BY priority, id"#)
+                .bind(planId)
+                .fetch_all(pool)
+                .await?
+        }
+        (None, Some(characters)) => characters,
+        (None, None) => return Err(ApiError::BadRequest("planId or characters required".to_string()))
+    };
+
+    if payload.refreshesPerDay.is_some_and(|refreshes| refreshes > MAX_REFRESHES_PER_DAY) {
+        return Err(ApiError::BadRequest(format!("refreshesPerDay can be at most {}", MAX_REFRESHES_PER_DAY)));
+    }
+
+    let ships = load_ships(pool).await?;
+    let mut errors = match payload.planId {
+        Some(_) => Vec::new(),
+        None => {
+            let baseIds: Vec<&str> = goals.iter().map(|goal| goal.baseId.as_str()).collect();
+            unknown_unit_errors(pool, &baseIds).await?
+        }
+    };
+    errors.extend(goals.iter()
+        .enumerate()
+        .flat_map(|(index, goal)| goal_errors(index, &goal.baseId, &ships, Some(goal.goalStars), None, None)));
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+
+    let promotion = load_star_promotion(pool).await?;
+    let mut characters: Vec<CharShardsNeeded> = Vec::new();
+    for goal in goals {
+        let currentStars: i64 = sqlx::query_scalar(r#"SELECT currentRarity FROM rosterUnit WHERE allyCode = ? AND definitionId = ?"#)
+            .bind(allyCode)
+            .bind(&goal.baseId)
+            .fetch_optional(pool)
+            .await?
+            .unwrap_or(0);
+
+        let farmLocations: Vec<DropLocation> = sqlx::query_as(r#"
+            SELECT campaignId, campaignMapId, campaignNodeDifficulty, campaignNodeId, campaignMissionId
+            FROM dropLocation WHERE itemId = ?
+        "#)
+        .bind(shard_id(&goal.baseId))
+        .fetch_all(pool)
+        .await?;
+
+        let (shards, credits) = promotion_cost(&promotion, currentStars as u32, goal.goalStars as u32);
+        let days = payload.energyPerDay.and_then(|energy| {
+            farm_days(shards, farmLocations.len(), energy, payload.refreshesPerDay.unwrap_or(0), state.config.shards_per_battle)
+        });
+
+        characters.push(CharShardsNeeded {
+            baseId: goal.baseId,
+            currentStars: currentStars as u32,
+            goalStars: goal.goalStars as u32,
+            shards,
+            credits,
+            farmLocations,
+            days
+        });
+    }
+
+    let days = characters.iter().map(|c| c.days).sum::<Option<f64>>()
+        .filter(|_| payload.energyPerDay.is_some());
+
+    Ok(Json(ShardsNeeded {
+        shards: characters.iter().map(|c| c.shards).sum(),
+        credits: characters.iter().map(|c| c.credits).sum(),
+        days,
+        characters
+    }))
+}
+
+/// Replaces the stored materials (shards, relic materials, etc.) and where they drop
+pub async fn setMaterialsToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    println!("setting {} materials", gamedata.material.len());
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(r#"DELETE FROM dropLocation WHERE itemId IN (SELECT materialId FROM material)"#)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(r#"DELETE FROM material"#).execute(&mut *tx).await?;
+
+    for material in &gamedata.material {
+        sqlx::query(r#"INSERT OR REPLACE INTO material (materialId, nameKey) VALUES (?, ?)"#)
+            .bind(&material.id)
+            .bind(&material.nameKey)
+            .execute(&mut *tx)
+            .await?;
+
+        for mission in &material.lookupMission {
+            let location = &mission.missionIdentifier;
+            sqlx::query(r#"
+                INSERT OR IGNORE INTO dropLocation
+                    (itemId, campaignId, campaignMapId, campaignNodeDifficulty, campaignNodeId, campaignMissionId)
+                VALUES (?, ?, ?, ?, ?, ?)
+            "#)
+            .bind(&material.id)
+            .bind(&location.campaignId)
+            .bind(&location.campaignMapId)
+            .bind(location.campaignNodeDifficulty)
+            .bind(&location.campaignNodeId)
+            .bind(&location.campaignMissionId)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    println!("materials added");
+
+    Ok(())
+}
+
+/// Replaces the stored star promotion costs with the game data tables. When either table is missing
+/// or doesn't cover every star level the stored costs are kept
+pub async fn setStarPromotionToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let column = |id: &str| -> Option<Vec<u64>> {
+        let table = gamedata.table.iter().find(|table| table.id == id)?;
+        (1..=MAX_STARS)
+            .map(|stars| {
+                let row = table.row.iter().find(|row| row.key == stars.to_string())?;
+                row.value.trim().parse().ok()
+            })
+            .collect()
+    };
+    let (Some(shards), Some(credits)) = (column(STAR_PROMOTION_SHARDS_TABLE), column(STAR_PROMOTION_CREDITS_TABLE)) else {
+        eprintln!("game data has no complete {} and {} tables, keeping the stored star promotion costs", STAR_PROMOTION_SHARDS_TABLE, STAR_PROMOTION_CREDITS_TABLE);
+        return Ok(());
+    };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(r#"DELETE FROM starPromotion"#).execute(&mut *tx).await?;
+    for (index, (shards, credits)) in shards.iter().zip(&credits).enumerate() {
+        sqlx::query(r#"INSERT INTO starPromotion (stars, shards, credits) VALUES (?, ?, ?)"#)
+            .bind(index as i64 + 1)
+            .bind(*shards as i64)
+            .bind(*credits as i64)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    println!("star promotion costs added");
+
+    Ok(())
+}
+
+// curl -X POST http://localhost:7474/shards_needed \
+//  -H "Content-Type: application/json" \
+//  -H "Authorization: Bearer <token>" \
+//  -d '{"planId": 1, "energyPerDay": 600, "refreshesPerDay": 1}'
@@ -0,0 +1,126 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use axum::extract::FromRef;
+use reqwest::Client;
+use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
+
+use crate::comlink::{ComlinkClient, FixtureComlink, HttpComlink};
+use crate::mailer::{LogMailer, Mailer, SmtpMailer};
+
+/// Settings read from the environment at startup, anything unset falls back to the docker compose defaults
+pub struct Config {
+    pub database_url: String,
+    pub comlink_url: String,
+    /// When set comlink responses are read from JSON files in this directory instead of calling comlink
+    pub comlink_fixtures: Option<PathBuf>,
+    pub asset_extractor_url: String,
+    pub jwt_secret: String,
+    /// SMTP server for outgoing email, when unset emails are only logged
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    /// When email is only logged, each message is also written here
+    pub mail_dir: Option<PathBuf>,
+    /// Users with these usernames are always admins, used to make the first admin
+    pub admin_usernames: Vec<String>,
+    /// Requests a minute each user can make to the endpoints that call comlink
+    pub rate_limit_per_minute: u32,
+    /// Requests a minute each client IP can make to them, whoever is signed in
+    pub rate_limit_per_ip_minute: u32,
+    /// How often one ally code can be refreshed from comlink
+    pub min_refresh_seconds: i64,
+    /// How often one guild can be refreshed from comlink
+    pub guild_refresh_seconds: i64,
+    /// Roster snapshots older than this many days are pruned, 0 keeps them forever
+    pub snapshot_retention_days: i64,
+    /// Average shards a hard node battle drops, used to estimate farming days
+    pub shards_per_battle: f64,
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let env_or = |key: &str, default: &str| std::env::var(key).unwrap_or_else(|_| default.to_string());
+
+        if std::env::var("JWT_SECRET").is_err() {
+            eprintln!("JWT_SECRET is not set, using the default secret");
+        }
+
+        Config {
+            database_url: env_or("DATABASE_URL", "sqlite:////data/mydb.sqlite"),
+            comlink_url: env_or("COMLINK_URL", "http://comlink:3000"),
+            comlink_fixtures: std::env::var("COMLINK_FIXTURES").ok().map(PathBuf::from),
+            asset_extractor_url: env_or("ASSET_EXTRACTOR_URL", "http://asset_extractor:8080"),
+            jwt_secret: env_or("JWT_SECRET", "mysecret"),
+            smtp_url: std::env::var("SMTP_URL").ok(),
+            mail_from: env_or("MAIL_FROM", "SWGOH Utils <noreply@localhost>"),
+            mail_dir: std::env::var("MAIL_DIR").ok().map(PathBuf::from),
+            admin_usernames: env_or("ADMIN_USERNAMES", "")
+                .split(',')
+                .map(|username| username.trim().to_string())
+                .filter(|username| !username.is_empty())
+                .collect(),
+            rate_limit_per_minute: env_or("RATE_LIMIT_PER_MINUTE", "30").parse().expect("RATE_LIMIT_PER_MINUTE must be a number"),
+            rate_limit_per_ip_minute: env_or("RATE_LIMIT_PER_IP_MINUTE", "120").parse().expect("RATE_LIMIT_PER_IP_MINUTE must be a number"),
+            min_refresh_seconds: env_or("MIN_REFRESH_SECONDS", "300").parse().expect("MIN_REFRESH_SECONDS must be a number"),
+            guild_refresh_seconds: env_or("GUILD_REFRESH_SECONDS", "3600").parse().expect("GUILD_REFRESH_SECONDS must be a number"),
+            snapshot_retention_days: env_or("SNAPSHOT_RETENTION_DAYS", "365").parse().expect("SNAPSHOT_RETENTION_DAYS must be a number"),
+            shards_per_battle: env_or("SHARDS_PER_BATTLE", "0.33").parse().expect("SHARDS_PER_BATTLE must be a number"),
+        }
+    }
+}
+
+/// Shared by every handler through axum's State, cloning it only clones handles
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub client: Client,
+    pub comlink: Arc<dyn ComlinkClient>,
+    pub mailer: Arc<dyn Mailer>,
+    pub config: Arc<Config>,
+}
+
+impl AppState {
+    /// Opens the database pool, creating the database file and its directory if they don't exist yet
+    pub async fn new(config: Config) -> Result<AppState, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(&config.database_url)?
+            .create_if_missing(true);
+
+        if let Some(dir) = options.get_filename().parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        let pool = SqlitePool::connect_with(options).await?;
+        let client = Client::new();
+
+        let comlink: Arc<dyn ComlinkClient> = match &config.comlink_fixtures {
+            Some(dir) => {
+                println!("serving comlink from fixtures in {}", dir.display());
+                Arc::new(FixtureComlink::new(dir))
+            }
+            None => Arc::new(HttpComlink::new(client.clone(), config.comlink_url.clone())),
+        };
+
+        let mailer: Arc<dyn Mailer> = match config.smtp_url.as_deref().map(|url| SmtpMailer::new(url, &config.mail_from)) {
+            Some(Ok(mailer)) => Arc::new(mailer),
+            Some(Err(e)) => {
+                eprintln!("{}, emails will only be logged", e);
+                Arc::new(LogMailer::new(config.mail_dir.clone()))
+            }
+            None => Arc::new(LogMailer::new(config.mail_dir.clone())),
+        };
+
+        Ok(AppState {
+            pool,
+            client,
+            comlink,
+            mailer,
+            config: Arc::new(config),
+        })
+    }
+}
+
+/// Lets handlers that only touch the database keep taking `State<SqlitePool>`
+impl FromRef<AppState> for SqlitePool {
+    fn from_ref(state: &AppState) -> SqlitePool {
+        state.pool.clone()
+    }
+}
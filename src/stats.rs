@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, HashMap};
+
+use sqlx::SqlitePool;
+
+use crate::mods::ModView;
+use crate::types::GameData;
+
+/// Everything the calculator works out, StatValues are indexed in this order
+pub const STAT_NAMES: [&str; 11] = [
+    "health", "protection", "speed", "offense", "defense",
+    "critdamage", "critchance", "potency", "tenacity", "accuracy", "critavoidance"
+];
+
+/// A unit's stats in STAT_NAMES order. Flat stats are plain numbers, the rest are fractions so 0.5 is 50%
+pub type StatValues = [f64; STAT_NAMES.len()];
+
+/// StatValues by name, how stats are sent to clients
+pub type Stats = BTreeMap<&'static str, f64>;
+
+/// Game data unitStatIds for the stats units start with
+const BASE_STAT_IDS: [(u32, &str); 8] = [
+    (1, "health"), (5, "speed"), (6, "offense"), (8, "defense"),
+    (16, "critdamage"), (17, "potency"), (18, "tenacity"), (28, "protection")
+];
+
+/// Which stat a mod stat adds to and whether it's a percentage of the base value
+fn mod_stat(statId: u32) -> Option<(&'static str, bool)> {
+    match statId {
+        1 => Some(("health", false)),
+        5 => Some(("speed", false)),
+        16 => Some(("critdamage", false)),
+        17 => Some(("potency", false)),
+        18 => Some(("tenacity", false)),
+        28 => Some(("protection", false)),
+        41 => Some(("offense", false)),
+        42 => Some(("defense", false)),
+        48 => Some(("offense", true)),
+        49 => Some(("defense", true)),
+        52 => Some(("accuracy", false)),
+        53 => Some(("critchance", false)),
+        54 => Some(("critavoidance", false)),
+        55 => Some(("health", true)),
+        56 => Some(("protection", true)),
+        _ => None
+    }
+}
+
+/// Set bonuses by setId, (pieces, stat, bonus with every piece at level 15, percentage of the base value).
+/// Sets with a mod under level 15 give half
+pub const SET_BONUSES: [(u32, usize, &str, f64, bool); 8] = [
+    (1, 2, "health", 0.10, true),
+    (2, 4, "offense", 0.15, true),
+    (3, 2, "defense", 0.25, true),
+    (4, 4, "speed", 0.10, true),
+    (5, 2, "critchance", 0.08, false),
+    (6, 4, "critdamage", 0.30, false),
+    (7, 2, "potency", 0.15, false),
+    (8, 2, "tenacity", 0.20, false)
+];
+
+/// Game data sends stat values times 10000 like mods
+const STAT_SCALE: f64 = 10000.0;
+
+/// Where a stat sits in StatValues
+pub fn stat_index(name: &str) -> Option<usize> {
+    STAT_NAMES.iter().position(|known| *known == name)
+}
+
+pub fn named(values: &StatValues) -> Stats {
+    STAT_NAMES.iter().copied().zip(values.iter().copied()).collect()
+}
+
+/// Replaces the stored stats every unit has at each gear tier
+pub async fn setUnitStatsToDB(gamedata: &GameData, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    println!("setting unit base stats");
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(r#"DELETE FROM unitBaseStat"#).execute(&mut *tx).await?;
+
+    for unit in &gamedata.units {
+        for unitTier in &unit.unitTier {
+            let Some(baseStat) = &unitTier.baseStat else { continue };
+            for stat in &baseStat.stat {
+                sqlx::query(r#"INSERT OR REPLACE INTO unitBaseStat (baseId, tier, statId, value) VALUES (?, ?, ?, ?)"#)
+                    .bind(&unit.baseId)
+                    .bind(unitTier.tier)
+                    .bind(stat.unitStatId)
+                    .bind(stat.raw_value())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The unit's stats without mods at its gear tier. Tiers missing from game data use the closest one below
+pub async fn base_stats(pool: &SqlitePool, baseId: &str, tier: i64) -> Result<StatValues, sqlx::Error> {
+    let rows: Vec<(i64, i64)> = sqlx::query_as(r#"
+        SELECT statId, value FROM unitBaseStat
+        WHERE baseId = ? AND tier = (SELECT MAX(tier) FROM unitBaseStat WHERE baseId = ? AND tier <= ?)
+    "#)
+    .bind(baseId)
+    .bind(baseId)
+    .bind(tier)
+    .fetch_all(pool)
+    .await?;
+    let values: HashMap<u32, f64> = rows.into_iter().map(|(statId, value)| (statId as u32, value as f64 / STAT_SCALE)).collect();
+
+    let mut base = [0.0; STAT_NAMES.len()];
+    for (statId, name) in BASE_STAT_IDS {
+        if let (Some(index), Some(value)) = (stat_index(name), values.get(&statId)) {
+            base[index] = *value;
+        }
+    }
+    Ok(base)
+}
+
+/// What one mod's primary and secondaries add to a unit with `base` stats
+pub fn mod_stats(base: &StatValues, statMod: &ModView) -> StatValues {
+    let mut added = [0.0; STAT_NAMES.len()];
+    let stats = std::iter::once(&statMod.primaryStat).chain(&statMod.secondaryStats);
+    for stat in stats {
+        let Some((name, percentOfBase)) = mod_stat(stat.statId) else { continue };
+        let Some(index) = stat_index(name) else { continue };
+        added[index] += if percentOfBase { base[index] * stat.value } else { stat.value };
+    }
+    added
+}
+
+/// What the completed sets among `mods` add to a unit with `base` stats
+pub fn set_stats(base: &StatValues, mods: &[&ModView]) -> StatValues {
+    let mut added = [0.0; STAT_NAMES.len()];
+    for (setId, pieces, name, bonus, percentOfBase) in SET_BONUSES {
+        let (count, maxed) = mods.iter()
+            .filter(|statMod| statMod.setId == setId)
+            .fold((0, true), |(count, maxed), statMod| (count + 1, maxed && statMod.level >= 15));
+        let completed = count / pieces;
+        let Some(index) = stat_index(name).filter(|_| completed > 0) else { continue };
+
+        let bonus = if maxed { bonus } else { bonus / 2.0 };
+        let amount = if percentOfBase { base[index] * bonus } else { bonus };
+        added[index] += amount * completed as f64;
+    }
+    added
+}
+
+/// The unit's stats with `mods` on: base stats plus every mod and the set bonuses. Gear pieces and relics
+/// aren't counted, so this is for comparing mod choices rather than matching the game exactly
+pub fn calculate(base: &StatValues, mods: &[&ModView]) -> StatValues {
+    let mut stats = *base;
+    for added in mods.iter().map(|statMod| mod_stats(base, statMod)).chain([set_stats(base, mods)]) {
+        for (stat, value) in stats.iter_mut().zip(added) {
+            *stat += value;
+        }
+    }
+    stats
+}
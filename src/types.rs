@@ -10,12 +10,28 @@ pub struct GameMetadata {
  
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GameData {
+    #[serde(default)]
     pub units: Vec<Unit>,
+    #[serde(default)]
+    pub equipment: Vec<Equipment>,
+    #[serde(default)]
+    pub recipe: Vec<Recipe>,
+    #[serde(default)]
+    pub material: Vec<Material>,
+    #[serde(default)]
+    pub relicTierDefinition: Vec<RelicTierDefinition>,
+    #[serde(default)]
+    pub skill: Vec<SkillDefinition>,
+    #[serde(default)]
+    pub table: Vec<Table>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Unit {
     pub baseId: String,
+    /// 1 for characters and 2 for ships, see SHIP_COMBAT_TYPE
+    #[serde(default = "character_combat_type")]
+    pub combatType: i64,
     pub categoryId: Vec<String>,
     pub relicDefinition: Option<RelicDefinition>,
     pub skillReference: Vec<Skill>,
@@ -24,9 +40,19 @@ pub struct Unit {
     pub crew: Vec<Crew>,
     pub iconPath: Option<String>
 }
+
+/// comlink's combatType for ships, characters are 1
+pub const SHIP_COMBAT_TYPE: i64 = 2;
+
+fn character_combat_type() -> i64 {
+    1
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct RelicDefinition {
-    pub texture: String
+    pub texture: String,
+    #[serde(default)]
+    pub relicTierDefinitionId: Vec<String>
 }
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Skill {
@@ -35,12 +61,105 @@ pub struct Skill {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Tier {
     pub tier: u32,
-    pub equipmentSet: Vec<String>
+    pub equipmentSet: Vec<String>,
+    /// The unit's stats at this gear tier, see stats.rs
+    #[serde(default)]
+    pub baseStat: Option<BaseStat>
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BaseStat {
+    #[serde(default)]
+    pub stat: Vec<UnitStat>
 }
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Crew {
     pub unitId: String
 }
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Equipment {
+    pub id: String,
+    pub nameKey: String,
+    pub tier: u32,
+    #[serde(default)]
+    pub mark: String,
+    #[serde(default)]
+    pub recipeId: String,
+    #[serde(default)]
+    pub lookupMission: Vec<LookupMission>
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LookupMission {
+    pub missionIdentifier: MissionIdentifier
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MissionIdentifier {
+    pub campaignId: String,
+    pub campaignMapId: String,
+    pub campaignNodeDifficulty: u32,
+    pub campaignNodeId: String,
+    pub campaignMissionId: String
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Material {
+    pub id: String,
+    pub nameKey: String,
+    #[serde(default)]
+    pub lookupMission: Vec<LookupMission>
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RelicTierDefinition {
+    pub id: String,
+    #[serde(default)]
+    pub recipeId: String
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Recipe {
+    pub id: String,
+    #[serde(default)]
+    pub ingredients: Vec<Ingredient>
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Ingredient {
+    pub id: String,
+    pub minQuantity: u32
+}
+/// Credits show up in equipment and relic recipes as an ingredient with this id
+pub const CREDITS_ID: &str = "GRIND";
+/// A key/value lookup table from the game data, values are always strings
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Table {
+    pub id: String,
+    #[serde(default)]
+    pub row: Vec<TableRow>
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TableRow {
+    pub key: String,
+    pub value: String
+}
+/// A skill's upgrade tiers, the first entry is the upgrade to level 2
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SkillDefinition {
+    pub id: String,
+    #[serde(default)]
+    pub tier: Vec<SkillTier>,
+    /// Which game mode the omicron works in, 0 when the skill has none
+    #[serde(default)]
+    pub omicronMode: u32
+}
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SkillTier {
+    #[serde(default)]
+    pub isZetaTier: bool,
+    #[serde(default)]
+    pub isOmicronTier: bool
+}
+impl SkillDefinition {
+    /// Index of the first tier flagged by `flag`, comparable with a player's skill tier
+    pub fn first_tier(&self, flag: impl Fn(&SkillTier) -> bool) -> Option<usize> {
+        self.tier.iter().position(flag)
+    }
+}
 
 
 //Player class 
@@ -63,11 +182,30 @@ pub struct Player {
     pub guildBannerLogo: String,
     pub selectedPlayerTitle: SelectedPlayerThing,
     pub selectedPlayerPortrait: SelectedPlayerThing,
+    /// Only sent by comlink, not kept in the database
+    #[serde(default, skip_serializing)]
+    pub unlockedPlayerTitle: Vec<SelectedPlayerThing>,
+    #[serde(default, skip_serializing)]
+    pub unlockedPlayerPortrait: Vec<SelectedPlayerThing>,
     pub playerRating: PlayerRating,
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
     #[serde(default = "current_time_string")]
     pub last_updated: String
 }
 #[derive(Deserialize, Serialize)]
+pub struct Inventory {
+    #[serde(default)]
+    pub material: Vec<InventoryItem>,
+    #[serde(default)]
+    pub equipment: Vec<InventoryItem>
+}
+#[derive(Deserialize, Serialize)]
+pub struct InventoryItem {
+    pub id: String,
+    pub quantity: u32
+}
+#[derive(Deserialize, Serialize)]
 pub struct SelectedPlayerThing {
     pub id: String
 }
@@ -91,9 +229,109 @@ pub struct RosterUnit {
     pub currentRarity: u32,
     pub currentLevel: u32,
     pub currentTier: u32,
-    pub relic: Option<Relic>
+    pub relic: Option<Relic>,
+    #[serde(default)]
+    pub skill: Vec<UnitSkill>,
+    /// Gear slotted at the current tier, cleared when the unit is promoted
+    #[serde(default)]
+    pub equipment: Vec<EquippedGear>,
+    #[serde(default)]
+    pub equippedStatMod: Vec<StatMod>,
+    /// Abilities bought for the unit, such as ultimates
+    #[serde(default)]
+    pub purchasedAbilityId: Vec<String>
+}
+/// `tier` indexes the skill definition's tiers so the skill level is tier + 2.
+/// hasZeta and hasOmicron are worked out from the stored skill definitions, comlink doesn't send them
+#[derive(Deserialize, Serialize)]
+pub struct UnitSkill {
+    pub id: String,
+    pub tier: i64,
+    #[serde(default)]
+    pub hasZeta: bool,
+    #[serde(default)]
+    pub hasOmicron: bool
+}
+#[derive(Deserialize, Serialize)]
+pub struct EquippedGear {
+    pub equipmentId: String,
+    /// 0 to 5, the position in the tier's equipmentSet
+    pub slot: u32
+}
+/// `definitionId` packs the set, rarity (pips) and slot as three digits, 451 is a 5 pip speed square
+#[derive(Deserialize, Serialize)]
+pub struct StatMod {
+    pub id: String,
+    pub definitionId: String,
+    pub level: u32,
+    /// Colour, 1 is grey (E) up to 5 gold (A)
+    pub tier: u32,
+    pub primaryStat: ModStat,
+    #[serde(default)]
+    pub secondaryStat: Vec<ModStat>
+}
+#[derive(Deserialize, Serialize)]
+pub struct ModStat {
+    pub stat: UnitStat,
+    /// Times a secondary was rolled into, primaries don't have it
+    #[serde(default)]
+    pub statRolls: u32
+}
+/// statValueDecimal is the value times 10000 sent as a string, percentages are fractions so 5% is "500"
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UnitStat {
+    pub unitStatId: u32,
+    pub statValueDecimal: String
+}
+impl UnitStat {
+    /// statValueDecimal as a number, still times 10000
+    pub fn raw_value(&self) -> i64 {
+        self.statValueDecimal.parse::<f64>().map(|value| value.round() as i64).unwrap_or(0)
+    }
+}
+impl StatMod {
+    pub fn set_rarity_slot(&self) -> Option<(u32, u32, u32)> {
+        let digits: Vec<u32> = self.definitionId.chars().map(|c| c.to_digit(10)).collect::<Option<_>>()?;
+        match digits[..] {
+            [setId, rarity, slot] => Some((setId, rarity, slot)),
+            _ => None
+        }
+    }
 }
 #[derive(Deserialize, Serialize)]
 pub struct Relic {
     pub currentTier: u32
-}
\ No newline at end of file
+}
+
+/// comlink offsets relic tiers by two, 1 is locked and 2 is R0.
+/// Returns the relic level players see in game, or None if relics aren't unlocked
+pub fn relic_level(currentTier: i64) -> Option<u32> {
+    if currentTier >= 2 {
+        Some((currentTier - 2) as u32)
+    } else {
+        None
+    }
+}
+//Guild class
+
+#[derive(Deserialize, Serialize)]
+pub struct GuildResponse {
+    pub guild: Guild
+}
+#[derive(Deserialize, Serialize)]
+pub struct Guild {
+    pub profile: GuildProfile,
+    pub member: Vec<GuildMember>
+}
+#[derive(Deserialize, Serialize)]
+pub struct GuildProfile {
+    pub id: String,
+    pub name: String,
+    pub memberCount: u32
+}
+#[derive(Deserialize, Serialize)]
+pub struct GuildMember {
+    pub playerId: String,
+    pub playerName: String,
+    pub memberLevel: u32
+}
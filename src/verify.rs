@@ -0,0 +1,238 @@
+use axum::extract::State;
+use chrono::{Duration, Utc};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{AuthBearer, AuthUser};
+use crate::comlink::PlayerLookup;
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::state::AppState;
+use crate::types::Player;
+
+/// Every account has these unlocked, so they're always an option
+const DEFAULT_PORTRAIT: &str = "PLAYERPORTRAIT_DEFAULT";
+const DEFAULT_TITLE: &str = "PLAYERTITLE_DEFAULT";
+
+/// How long a player has to make the change in game
+const CHALLENGE_MINUTES: i64 = 30;
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeKind {
+    Portrait,
+    Title
+}
+
+impl ChallengeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChallengeKind::Portrait => "portrait",
+            ChallengeKind::Title => "title",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<ChallengeKind> {
+        match kind {
+            "portrait" => Some(ChallengeKind::Portrait),
+            "title" => Some(ChallengeKind::Title),
+            _ => None
+        }
+    }
+
+    /// The id the player has selected right now
+    fn selected<'a>(&self, player: &'a Player) -> &'a str {
+        match self {
+            ChallengeKind::Portrait => &player.selectedPlayerPortrait.id,
+            ChallengeKind::Title => &player.selectedPlayerTitle.id,
+        }
+    }
+
+    /// Everything the player could select, the default included
+    fn unlocked<'a>(&self, player: &'a Player) -> Vec<&'a str> {
+        let (default, unlocked) = match self {
+            ChallengeKind::Portrait => (DEFAULT_PORTRAIT, &player.unlockedPlayerPortrait),
+            ChallengeKind::Title => (DEFAULT_TITLE, &player.unlockedPlayerTitle),
+        };
+        let mut ids: Vec<&str> = unlocked.iter().map(|thing| thing.id.as_str()).collect();
+        if !ids.contains(&default) {
+            ids.push(default);
+        }
+        ids
+    }
+}
+
+/// Something only the owner of the ally code can change, the target is picked at random for every challenge
+#[derive(Serialize)]
+pub struct Challenge {
+    pub kind: ChallengeKind,
+    pub targetId: String,
+    pub previousId: String,
+    pub expiresAt: i64,
+    pub message: String
+}
+
+impl Challenge {
+    fn new(kind: ChallengeKind, targetId: String, previousId: String, expiresAt: i64) -> Challenge {
+        let message = format!("Set your {} to {} in game, then call /verify", kind.as_str(), targetId);
+        Challenge { kind, targetId, previousId, expiresAt, message }
+    }
+
+    fn passed(&self, player: &Player) -> bool {
+        self.kind.selected(player) == self.targetId
+    }
+}
+
+/// Asks the player to select a random portrait or title they have unlocked but not selected, so someone
+/// else can't guess the target and wait for the owner to pick it
+fn pick_challenge(player: &Player, expiresAt: i64) -> Result<Challenge, ApiError> {
+    let options: Vec<(ChallengeKind, &str)> = [ChallengeKind::Portrait, ChallengeKind::Title]
+        .into_iter()
+        .flat_map(|kind| {
+            let selected = kind.selected(player);
+            kind.unlocked(player)
+                .into_iter()
+                .filter(move |id| *id != selected)
+                .map(move |id| (kind, id))
+        })
+        .collect();
+
+    let (kind, targetId) = options
+        .choose(&mut rand::thread_rng())
+        .ok_or_else(|| ApiError::BadRequest("No other portrait or title to switch to, unlock one in game first".to_string()))?;
+
+    Ok(Challenge::new(*kind, targetId.to_string(), kind.selected(player).to_string(), expiresAt))
+}
+
+/// Only one user can prove they own an ally code
+async fn check_not_verified_elsewhere(state: &AppState, user: &AuthUser) -> Result<(), ApiError> {
+    let taken: bool = sqlx::query_scalar(r#"
+        SELECT EXISTS(SELECT 1 FROM linkedAccount WHERE allyCode = ? AND userId != ? AND verified = 1)
+    "#)
+    .bind(&user.allyCode)
+    .bind(user.userId)
+    .fetch_one(&state.pool)
+    .await?;
+
+    match taken {
+        true => Err(ApiError::Conflict(format!("Ally code {} is already verified by another user", user.allyCode))),
+        false => Ok(()),
+    }
+}
+
+async fn load_challenge(state: &AppState, user: &AuthUser) -> Result<Option<Challenge>, ApiError> {
+    let row: Option<(String, Option<String>, String, i64)> = sqlx::query_as(r#"
+        SELECT kind, targetId, previousId, expiresAt FROM verificationChallenge WHERE userId = ? AND allyCode = ?
+    "#)
+    .bind(user.userId)
+    .bind(&user.allyCode)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    // Challenges from before targets were required have none and can't be passed
+    Ok(row.and_then(|(kind, targetId, previousId, expiresAt)| {
+        Some(Challenge::new(ChallengeKind::parse(&kind)?, targetId?, previousId, expiresAt))
+    }))
+}
+
+#[derive(Serialize)]
+pub struct VerificationStatus {
+    pub verified: bool,
+    pub challenge: Option<Challenge>
+}
+
+/// GET /verify - whether the user has proven they own the request's ally code, and the open challenge if there is one
+pub async fn verification_status(State(state): State<AppState>, AuthBearer(user): AuthBearer) -> Result<Json<VerificationStatus>, ApiError> {
+    let challenge = match user.verified {
+        true => None,
+        false => load_challenge(&state, &user).await?.filter(|c| c.expiresAt >= Utc::now().timestamp()),
+    };
+
+    Ok(Json(VerificationStatus { verified: user.verified, challenge }))
+}
+
+/// POST /verify/challenge - starts a new challenge against the player's current portrait and title,
+/// replacing any open one. Refused once another user has verified the ally code
+pub async fn create_challenge(State(state): State<AppState>, AuthBearer(user): AuthBearer) -> Result<Json<Challenge>, ApiError> {
+    if user.verified {
+        return Err(ApiError::Conflict(format!("Ally code {} is already verified", user.allyCode)));
+    }
+    check_not_verified_elsewhere(&state, &user).await?;
+
+    let player = state.comlink.player(&PlayerLookup::AllyCode(user.allyCode.clone())).await?;
+    let challenge = pick_challenge(&player, (Utc::now() + Duration::minutes(CHALLENGE_MINUTES)).timestamp())?;
+
+    sqlx::query(r#"
+        INSERT INTO verificationChallenge (userId, allyCode, kind, targetId, previousId, expiresAt)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(userId, allyCode) DO UPDATE SET
+            kind = excluded.kind,
+            targetId = excluded.targetId,
+            previousId = excluded.previousId,
+            expiresAt = excluded.expiresAt
+    "#)
+    .bind(user.userId)
+    .bind(&user.allyCode)
+    .bind(challenge.kind.as_str())
+    .bind(&challenge.targetId)
+    .bind(&challenge.previousId)
+    .bind(challenge.expiresAt)
+    .execute(&state.pool)
+    .await?;
+
+    Ok(Json(challenge))
+}
+
+/// POST /verify - fetches the player fresh from comlink and marks the ally code verified if the challenge is met
+pub async fn verify(State(state): State<AppState>, AuthBearer(user): AuthBearer) -> Result<Json<VerificationStatus>, ApiError> {
+    if user.verified {
+        return Ok(Json(VerificationStatus { verified: true, challenge: None }));
+    }
+    check_not_verified_elsewhere(&state, &user).await?;
+
+    let challenge = load_challenge(&state, &user)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("No verification challenge, request one from /verify/challenge".to_string()))?;
+    if challenge.expiresAt < Utc::now().timestamp() {
+        return Err(ApiError::BadRequest("Verification challenge expired, request a new one".to_string()));
+    }
+
+    let player = state.comlink.player(&PlayerLookup::AllyCode(user.allyCode.clone())).await?;
+    if !challenge.passed(&player) {
+        return Err(ApiError::BadRequest(format!(
+            "Your {} is still {}. {}",
+            challenge.kind.as_str(),
+            challenge.kind.selected(&player),
+            challenge.message
+        )));
+    }
+
+    // Checked again in the update in case another user verified while comlink was asked
+    let mut tx = state.pool.begin().await?;
+    let result = sqlx::query(r#"
+        UPDATE linkedAccount SET verified = 1
+        WHERE userId = ? AND allyCode = ?
+            AND NOT EXISTS(SELECT 1 FROM linkedAccount WHERE allyCode = ? AND userId != ? AND verified = 1)
+    "#)
+    .bind(user.userId)
+    .bind(&user.allyCode)
+    .bind(&user.allyCode)
+    .bind(user.userId)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::Conflict(format!("Ally code {} is already verified by another user", user.allyCode)));
+    }
+    sqlx::query(r#"DELETE FROM verificationChallenge WHERE userId = ? AND allyCode = ?"#)
+        .bind(user.userId)
+        .bind(&user.allyCode)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    println!("verified ally code {} for user {}", user.allyCode, user.userId);
+    Ok(Json(VerificationStatus { verified: true, challenge: None }))
+}
+
+//curl -X POST http://localhost:7474/verify/challenge -H "Authorization: Bearer <token>"
+//curl -X POST http://localhost:7474/verify -H "Authorization: Bearer <token>"
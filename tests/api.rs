@@ -0,0 +1,1477 @@
+#![allow(non_snake_case)]
+use std::path::{Path, PathBuf};
+
+use axum::{
+    Router, body::Body, http::{Method, Request, StatusCode, header}
+};
+use http_body_util::BodyExt;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+use swgoh_utils_api::{
+    app,
+    gamedata::{add_images_gamedata, fetch_game_data, store_game_data},
+    setup::dbSetup,
+    state::{AppState, Config},
+};
+
+/// Ally code of fixtures/comlink/player-123456789.json
+const ALLY_CODE: &str = "123456789";
+
+/// A fresh API over a temporary SQLite file, with comlink served from a copy of fixtures/comlink
+/// in `comlink/` so tests can change a player, emails written to `mail/` and the fixture game data already stored.
+/// Keep the TempDir alive for the whole test
+async fn test_app() -> (Router, TempDir) {
+    test_app_with(|_| {}).await
+}
+
+/// `test_app` with the config changed first. Rate limits are off unless a test turns them on
+async fn test_app_with(configure: impl FnOnce(&mut Config)) -> (Router, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let fixtures = dir.path().join("comlink");
+    std::fs::create_dir(&fixtures).unwrap();
+    for entry in std::fs::read_dir(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/comlink")).unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), fixtures.join(entry.file_name())).unwrap();
+    }
+
+    let mut config = Config {
+        database_url: format!("sqlite://{}", dir.path().join("test.sqlite").display()),
+        comlink_url: "http://comlink.invalid".to_string(),
+        comlink_fixtures: Some(fixtures),
+        asset_extractor_url: "http://asset_extractor.invalid".to_string(),
+        jwt_secret: "test-secret".to_string(),
+        smtp_url: None,
+        mail_from: "test@localhost".to_string(),
+        mail_dir: Some(dir.path().join("mail")),
+        admin_usernames: vec!["admin".to_string()],
+        rate_limit_per_minute: 10_000,
+        rate_limit_per_ip_minute: 10_000,
+        min_refresh_seconds: 0,
+        guild_refresh_seconds: 0,
+        snapshot_retention_days: 0,
+        shards_per_battle: 0.5,
+    };
+    configure(&mut config);
+
+    let state = AppState::new(config).await.unwrap();
+    dbSetup(&state.pool).await.unwrap();
+
+    let (_, gamedata) = fetch_game_data(state.comlink.as_ref()).await.unwrap();
+    store_game_data(&add_images_gamedata(gamedata), &state.pool).await;
+
+    (app(state), dir)
+}
+
+async fn send(app: &Router, method: Method, uri: &str, token: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    send_as(app, method, uri, token, None, body).await
+}
+
+/// `send` acting as one of the user's linked ally codes
+async fn send_as(app: &Router, method: Method, uri: &str, token: Option<&str>, allyCode: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    if let Some(allyCode) = allyCode {
+        request = request.header("X-Ally-Code", allyCode);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => request.body(Body::empty()).unwrap(),
+    };
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+    (status, body)
+}
+
+async fn sign_up(app: &Router, username: &str, allyCode: &str) -> (StatusCode, Value) {
+    send(app, Method::POST, "/signUp", None, Some(json!({
+        "username": username,
+        "password": "hunter2",
+        "allyCode": allyCode,
+        "email": format!("{}@example.com", username)
+    }))).await
+}
+
+async fn sign_in(app: &Router, username: &str, password: &str) -> (StatusCode, Value) {
+    send(app, Method::POST, "/signIn", None, Some(json!({
+        "username": username,
+        "password": password
+    }))).await
+}
+
+/// Signs up a fresh user for the fixture player and returns their token
+async fn token(app: &Router) -> String {
+    let (status, _) = sign_up(app, "fixture", ALLY_CODE).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = sign_in(app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::OK);
+    body["token"].as_str().unwrap().to_string()
+}
+
+/// Rewrites the fixture player comlink sends back, as if they played the game in between
+fn play_in_game(dir: &Path, allyCode: &str, change: impl FnOnce(&mut Value)) {
+    let path = dir.join("comlink").join(format!("player-{}.json", allyCode));
+    let mut player: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    change(&mut player);
+    std::fs::write(&path, player.to_string()).unwrap();
+}
+
+/// Changes what the fixture player has selected in game, as if they did it themselves
+fn select_in_game(dir: &Path, allyCode: &str, kind: &str, id: &str) {
+    let field = match kind {
+        "portrait" => "selectedPlayerPortrait",
+        _ => "selectedPlayerTitle",
+    };
+    play_in_game(dir, allyCode, |player| player[field]["id"] = json!(id));
+}
+
+/// Completes the verification challenge for the token's ally code
+async fn verify(app: &Router, dir: &Path, token: &str, allyCode: &str) {
+    let (status, challenge) = send_as(app, Method::POST, "/verify/challenge", Some(token), Some(allyCode), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let kind = challenge["kind"].as_str().unwrap();
+    select_in_game(dir, allyCode, kind, challenge["targetId"].as_str().unwrap());
+
+    let (status, body) = send_as(app, Method::POST, "/verify", Some(token), Some(allyCode), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["verified"], true);
+}
+
+/// Like `token` but the fixture player has also proven they own the ally code
+async fn verified_token(app: &Router, dir: &Path) -> String {
+    let token = token(app).await;
+    verify(app, dir, &token, ALLY_CODE).await;
+    token
+}
+
+#[tokio::test]
+async fn sign_up_and_sign_in_on_a_fresh_database() {
+    let (app, _dir) = test_app().await;
+
+    let (status, _) = sign_up(&app, "fixture", ALLY_CODE).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = sign_in(&app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].as_str().is_some_and(|token| !token.is_empty()));
+}
+
+#[tokio::test]
+async fn sign_up_rejects_a_taken_username() {
+    let (app, _dir) = test_app().await;
+    sign_up(&app, "fixture", ALLY_CODE).await;
+
+    let (status, body) = sign_up(&app, "fixture", ALLY_CODE).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+}
+
+#[tokio::test]
+async fn sign_up_rejects_an_invalid_email() {
+    let (app, _dir) = test_app().await;
+
+    for email in ["not-an-email", "../../escape", "two@@example.com"] {
+        let (status, body) = send(&app, Method::POST, "/signUp", None, Some(json!({
+            "username": "fixture",
+            "password": "hunter2",
+            "allyCode": ALLY_CODE,
+            "email": email
+        }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", email);
+        assert_eq!(body["error"]["code"], "bad_request");
+    }
+}
+
+#[tokio::test]
+async fn sign_up_with_an_unknown_ally_code_creates_no_user() {
+    let (app, _dir) = test_app().await;
+
+    let (status, body) = sign_up(&app, "nobody", "111111111").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "not_found");
+
+    let (status, _) = sign_in(&app, "nobody", "hunter2").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn sign_in_rejects_a_wrong_password() {
+    let (app, _dir) = test_app().await;
+    sign_up(&app, "fixture", ALLY_CODE).await;
+
+    let (status, body) = sign_in(&app, "fixture", "wrong").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn account_requires_a_valid_token() {
+    let (app, _dir) = test_app().await;
+
+    let (status, _) = send(&app, Method::GET, "/account", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = send(&app, Method::GET, "/account", Some("not-a-token"), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "unauthorized");
+}
+
+#[tokio::test]
+async fn account_returns_the_stored_roster() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["allyCode"], ALLY_CODE);
+    assert_eq!(body["name"], "Fixture Player");
+    assert_eq!(body["rosterUnit"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn refresh_account_syncs_from_comlink() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, body) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["allyCode"], ALLY_CODE);
+    assert_eq!(body["guildId"], "fixture-guild");
+}
+
+#[tokio::test]
+async fn characters_lists_every_unit() {
+    let (app, _dir) = test_app().await;
+
+    let (status, body) = send(&app, Method::POST, "/characters", None, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let mut baseIds: Vec<&str> = body.as_array().unwrap().iter().map(|c| c["baseId"].as_str().unwrap()).collect();
+    baseIds.sort();
+    assert_eq!(baseIds, ["CAPITALNEGOTIATOR", "GENERALKENOBI", "GRANDMASTERYODA"]);
+}
+
+#[tokio::test]
+async fn characters_returns_a_single_unit() {
+    let (app, _dir) = test_app().await;
+
+    let (status, body) = send(&app, Method::POST, "/characters", None, Some(json!({ "charId": "GENERALKENOBI" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["baseId"], "GENERALKENOBI");
+    assert_eq!(body[0]["unitTier"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn characters_unknown_id_is_not_found() {
+    let (app, _dir) = test_app().await;
+
+    let (status, body) = send(&app, Method::POST, "/characters", None, Some(json!({ "charId": "NOTAUNIT" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn set_plan_then_get_plan_shows_progress() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::POST, "/set_plan", Some(&token), Some(json!({
+        "name": "Jedi",
+        "icon": "assets/tex.charui_obiwangeneral.png",
+        "characters": [
+            { "baseId": "GENERALKENOBI", "name": "General Kenobi", "goalStars": 7, "goalGear": 13, "goalRelic": 5 },
+            { "baseId": "GRANDMASTERYODA", "name": "Grand Master Yoda", "goalStars": 7, "goalGear": 13, "goalRelic": 0 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let planId = body["id"].as_i64().unwrap();
+
+    let (status, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let plans = body.as_array().unwrap();
+    assert_eq!(plans.len(), 1);
+    assert_eq!(plans[0]["id"], planId);
+    assert_eq!(plans[0]["name"], "Jedi");
+
+    // Kenobi is 7 stars, G13 and R5 in the fixture, so his goal is already met
+    let characters = plans[0]["characters"].as_array().unwrap();
+    assert_eq!(characters[0]["baseId"], "GENERALKENOBI");
+    assert_eq!(characters[0]["completion"], 100.0);
+    assert_eq!(characters[1]["currentStars"], 5);
+    assert!(characters[1]["completion"].as_f64().unwrap() < 100.0);
+}
+
+#[tokio::test]
+async fn set_plan_rejects_invalid_goals() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::POST, "/set_plan", Some(&token), Some(json!({
+        "name": "Broken",
+        "icon": "",
+        "characters": [
+            { "baseId": "NOTAUNIT", "name": "Nobody", "goalStars": 7, "goalGear": 13, "goalRelic": 0 },
+            { "baseId": "GENERALKENOBI", "name": "General Kenobi", "goalStars": 8, "goalGear": 13, "goalRelic": 0 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["code"], "validation_failed");
+
+    let fields: Vec<&str> = body["error"]["details"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["baseId", "goalStars"]);
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn ship_goals_skip_gear_and_relics() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::POST, "/set_plan", Some(&token), Some(json!({
+        "name": "Broken",
+        "icon": "",
+        "characters": [
+            { "baseId": "CAPITALNEGOTIATOR", "name": "Negotiator", "goalStars": 7, "goalGear": 13, "goalRelic": 5 },
+            { "baseId": "GENERALKENOBI", "name": "General Kenobi", "goalStars": 7, "goalGear": 0, "goalRelic": 0 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let details: Vec<(i64, &str)> = body["error"]["details"].as_array().unwrap().iter()
+        .map(|e| (e["index"].as_i64().unwrap(), e["field"].as_str().unwrap()))
+        .collect();
+    assert_eq!(details, [(0, "goalGear"), (0, "goalRelic"), (1, "goalGear")]);
+
+    let (status, body) = send(&app, Method::POST, "/set_plan", Some(&token), Some(json!({
+        "name": "Capital ships",
+        "icon": "",
+        "characters": [
+            { "baseId": "CAPITALNEGOTIATOR", "name": "Negotiator", "goalStars": 7, "goalGear": 0, "goalRelic": 0 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let planId = body["id"].as_i64().unwrap();
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    let charPlanId = body[0]["characters"][0]["id"].as_i64().unwrap();
+    let path = format!("/plan/{}/character/{}", planId, charPlanId);
+    let (status, _) = send(&app, Method::PATCH, &path, Some(&token), Some(json!({ "goalGear": 12 }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = send(&app, Method::PATCH, &path, Some(&token), Some(json!({ "goalStars": 6 }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
+
+/// Creates a plan with Kenobi and Yoda goals, returning its id
+async fn jedi_plan(app: &Router, token: &str, name: &str) -> i64 {
+    let (status, body) = send(app, Method::POST, "/set_plan", Some(token), Some(json!({
+        "name": name,
+        "icon": "",
+        "characters": [
+            { "baseId": "GENERALKENOBI", "name": "General Kenobi", "goalStars": 7, "goalGear": 13, "goalRelic": 5 },
+            { "baseId": "GRANDMASTERYODA", "name": "Grand Master Yoda", "goalStars": 7, "goalGear": 13, "goalRelic": 0 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    body["id"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn plans_can_only_be_changed_by_their_owner() {
+    let (app, dir) = test_app().await;
+    let owner = verified_token(&app, dir.path()).await;
+    let other = other_user(&app, "member", MEMBER_ALLY_CODE).await;
+    verify(&app, dir.path(), &other, MEMBER_ALLY_CODE).await;
+
+    let planId = jedi_plan(&app, &owner, "Jedi").await;
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&owner), None).await;
+    let charPlanId = body[0]["characters"][0]["id"].as_i64().unwrap();
+    let plan = format!("/plan/{}", planId);
+    let character = format!("/plan/{}/character/{}", planId, charPlanId);
+    let goal = json!({ "baseId": "GENERALKENOBI", "name": "Kenobi", "goalStars": 7, "goalGear": 12, "goalRelic": 0 });
+
+    let requests = [
+        (Method::PUT, plan.clone(), Some(json!({ "name": "Mine", "icon": "" }))),
+        (Method::PATCH, plan.clone(), Some(json!({ "name": "Mine" }))),
+        (Method::PUT, format!("{}/order", plan), Some(json!({ "order": [charPlanId] }))),
+        (Method::PUT, character.clone(), Some(goal)),
+        (Method::PATCH, character.clone(), Some(json!({ "goalGear": 12 }))),
+        (Method::DELETE, character.clone(), None),
+        (Method::DELETE, plan.clone(), None),
+    ];
+    for (method, uri, body) in requests {
+        let (status, _) = send(&app, method.clone(), &uri, Some(&other), body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+    }
+
+    // Other accounts' plans look like they don't exist when ordering
+    let (status, _) = send(&app, Method::PUT, "/plans/order", Some(&other), Some(json!({ "order": [planId] }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::DELETE, "/plan/999", Some(&other), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&owner), None).await;
+    assert_eq!(body[0]["name"], "Jedi");
+    assert_eq!(body[0]["characters"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn plans_and_goals_can_be_reordered() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let first = jedi_plan(&app, &token, "First").await;
+    let second = jedi_plan(&app, &token, "Second").await;
+    let (status, _) = send(&app, Method::PUT, "/plans/order", Some(&token), Some(json!({ "order": [second, first] }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert_eq!(body[0]["name"], "Second");
+    assert_eq!(body[1]["name"], "First");
+
+    let characters: Vec<i64> = body[1]["characters"].as_array().unwrap().iter().map(|c| c["id"].as_i64().unwrap()).collect();
+    let uri = format!("/plan/{}/order", first);
+    let (status, _) = send(&app, Method::PUT, &uri, Some(&token), Some(json!({ "order": [characters[1], characters[0]] }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert_eq!(body[1]["characters"][0]["baseId"], "GRANDMASTERYODA");
+
+    // Listing an id twice is rejected and changes nothing
+    let (status, body) = send(&app, Method::PUT, "/plans/order", Some(&token), Some(json!({ "order": [first, first] }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["details"][0]["index"], 1);
+    let (status, _) = send(&app, Method::PUT, &uri, Some(&token), Some(json!({ "order": [characters[0], characters[0]] }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert_eq!(body[0]["name"], "Second");
+    assert_eq!(body[1]["characters"][0]["baseId"], "GRANDMASTERYODA");
+}
+
+#[tokio::test]
+async fn shards_needed_uses_the_game_datas_promotion_costs() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    // Yoda is 5 stars and Kenobi 7, the fixture's hard node drops 0.5 shards a battle
+    let (status, body) = send(&app, Method::POST, "/shards_needed", Some(&token), Some(json!({
+        "characters": [
+            { "baseId": "GENERALKENOBI", "goalStars": 7 },
+            { "baseId": "GRANDMASTERYODA", "goalStars": 7 }
+        ],
+        "energyPerDay": 600,
+        "refreshesPerDay": 1
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["characters"][0]["shards"], 0);
+    assert_eq!(body["characters"][0]["days"], 0.0);
+    let yoda = &body["characters"][1];
+    assert_eq!(yoda["currentStars"], 5);
+    assert_eq!(yoda["shards"], 185);
+    assert_eq!(yoda["credits"], 750000);
+    assert_eq!(yoda["farmLocations"].as_array().unwrap().len(), 1);
+    // 10 battles a day on one node with a refresh, 5 shards a day
+    assert_eq!(yoda["days"], 37.0);
+    assert_eq!(body["shards"], 185);
+    assert_eq!(body["days"], 37.0);
+
+    // Without energy there's no estimate, and a locked unit starts from unlocking
+    let (status, body) = send(&app, Method::POST, "/shards_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "CAPITALNEGOTIATOR", "goalStars": 2 }]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["shards"], 25);
+    assert_eq!(body["credits"], 10000);
+    assert!(body["days"].is_null());
+
+    let (status, body) = send(&app, Method::POST, "/shards_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GRANDMASTERYODA", "goalStars": 8 }]
+    }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["details"][0]["field"], "goalStars");
+
+    let (status, _) = send(&app, Method::POST, "/shards_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GRANDMASTERYODA", "goalStars": 7 }],
+        "energyPerDay": 600,
+        "refreshesPerDay": u32::MAX
+    }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A database that has never loaded game data still has the known costs
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}?mode=rwc", dir.path().join("fresh.sqlite").display())).await.unwrap();
+    dbSetup(&pool).await.unwrap();
+    let costs: Vec<(i64, i64)> = sqlx::query_as("SELECT shards, credits FROM starPromotion ORDER BY stars").fetch_all(&pool).await.unwrap();
+    assert_eq!(costs.len(), 7);
+    assert_eq!(costs[6], (100, 500000));
+}
+
+#[tokio::test]
+async fn relics_needed_adds_up_recipes_against_the_inventory() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    // Kenobi is already R5 and Yoda hasn't unlocked relics, so only Yoda's R1 and R2 recipes count
+    let (status, body) = send(&app, Method::POST, "/relics_needed", Some(&token), Some(json!({
+        "characters": [
+            { "baseId": "GENERALKENOBI", "goalRelic": 5 },
+            { "baseId": "GRANDMASTERYODA", "goalRelic": 2 }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["hasInventory"], true);
+    assert_eq!(body["characters"][0]["currentRelic"], 5);
+    assert!(body["characters"][0]["materials"].as_array().unwrap().is_empty());
+    assert_eq!(body["characters"][1]["currentRelic"], 0);
+    assert_eq!(body["characters"][1]["credits"], 30000);
+    assert_eq!(body["credits"], 30000);
+    assert_eq!(body["materials"], json!([
+        { "materialId": "relic_salvage_1", "quantity": 60, "owned": 25, "missing": 35 },
+        { "materialId": "relic_salvage_2", "quantity": 5, "owned": 0, "missing": 5 }
+    ]));
+
+    // A saved plan works the same way
+    let planId = jedi_plan(&app, &token, "Jedi").await;
+    let (status, body) = send(&app, Method::POST, "/relics_needed", Some(&token), Some(json!({ "planId": planId }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["credits"], 0);
+
+    let (status, body) = send(&app, Method::POST, "/relics_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GRANDMASTERYODA", "goalRelic": 10 }]
+    }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["details"][0]["field"], "goalRelic");
+}
+
+#[tokio::test]
+async fn refresh_rotates_the_refresh_token() {
+    let (app, _dir) = test_app().await;
+    sign_up(&app, "fixture", ALLY_CODE).await;
+    let (_, body) = sign_in(&app, "fixture", "hunter2").await;
+    let refreshToken = body["refreshToken"].as_str().unwrap().to_string();
+
+    let (status, body) = send(&app, Method::POST, "/refresh", None, Some(json!({ "refreshToken": refreshToken }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(body["refreshToken"], refreshToken);
+
+    let token = body["token"].as_str().unwrap();
+    let (status, _) = send(&app, Method::GET, "/account", Some(token), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reusing_a_refresh_token_ends_the_session() {
+    let (app, _dir) = test_app().await;
+    sign_up(&app, "fixture", ALLY_CODE).await;
+    let (_, body) = sign_in(&app, "fixture", "hunter2").await;
+    let refreshToken = body["refreshToken"].as_str().unwrap().to_string();
+
+    let (_, rotated) = send(&app, Method::POST, "/refresh", None, Some(json!({ "refreshToken": refreshToken }))).await;
+
+    let (status, body) = send(&app, Method::POST, "/refresh", None, Some(json!({ "refreshToken": refreshToken }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "unauthorized");
+
+    // The token it was rotated into is gone too, along with its access token
+    let (status, _) = send(&app, Method::POST, "/refresh", None, Some(json!({ "refreshToken": rotated["refreshToken"] }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::GET, "/account", rotated["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn sign_out_revokes_only_that_session() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+    let (_, other) = sign_in(&app, "fixture", "hunter2").await;
+
+    let (status, _) = send(&app, Method::POST, "/signOut", Some(&token), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::GET, "/account", other["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sign_out_everywhere_revokes_every_session() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+    let (_, other) = sign_in(&app, "fixture", "hunter2").await;
+
+    let (status, _) = send(&app, Method::POST, "/signOut", Some(&token), Some(json!({ "everywhere": true }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(&app, Method::GET, "/account", other["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::POST, "/refresh", None, Some(json!({ "refreshToken": other["refreshToken"] }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Signing in again afterwards still works
+    let (_, body) = sign_in(&app, "fixture", "hunter2").await;
+    let (status, _) = send(&app, Method::GET, "/account", body["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn unverified_accounts_only_see_public_data() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, _) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    for uri in ["/get_plan", "/history", "/mods"] {
+        let (status, _) = send(&app, Method::GET, uri, Some(&token), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    let (status, body) = send(&app, Method::POST, "/set_plan", Some(&token), Some(json!({
+        "name": "Jedi", "icon": "", "characters": []
+    }))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn verification_needs_the_change_in_game() {
+    let (app, dir) = test_app().await;
+    let token = token(&app).await;
+
+    // Anything the fixture player has unlocked but not selected can be asked for
+    let (status, challenge) = send(&app, Method::POST, "/verify/challenge", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!([
+        "PLAYERPORTRAIT_DEFAULT", "PLAYERPORTRAIT_KENOBI", "PLAYERPORTRAIT_REPUBLIC",
+        "PLAYERTITLE_DEFAULT", "PLAYERTITLE_GENERAL"
+    ].contains(&challenge["targetId"].as_str().unwrap()));
+
+    let (status, body) = send(&app, Method::POST, "/verify", Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    select_in_game(dir.path(), ALLY_CODE, challenge["kind"].as_str().unwrap(), challenge["targetId"].as_str().unwrap());
+    let (status, _) = send(&app, Method::POST, "/verify", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, body) = send(&app, Method::GET, "/verify", Some(&token), None).await;
+    assert_eq!(body["verified"], true);
+    let (status, _) = send(&app, Method::POST, "/verify/challenge", Some(&token), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn verification_targets_something_unlocked() {
+    let (app, dir) = test_app().await;
+    sign_up(&app, "officer", "987654321").await;
+    let (_, body) = sign_in(&app, "officer", "hunter2").await;
+    let token = body["token"].as_str().unwrap();
+
+    // Both defaults are selected, the only other unlock is the officer portrait
+    let (_, challenge) = send(&app, Method::POST, "/verify/challenge", Some(token), None).await;
+    assert_eq!(challenge["kind"], "portrait");
+    assert_eq!(challenge["targetId"], "PLAYERPORTRAIT_OFFICER");
+    assert_eq!(challenge["previousId"], "PLAYERPORTRAIT_DEFAULT");
+
+    // Any other change doesn't count
+    select_in_game(dir.path(), "987654321", "portrait", "PLAYERPORTRAIT_SOMETHING_ELSE");
+    let (status, _) = send(&app, Method::POST, "/verify", Some(token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    verify(&app, dir.path(), token, "987654321").await;
+}
+
+#[tokio::test]
+async fn verification_needs_something_to_switch_to() {
+    let (app, dir) = test_app().await;
+    play_in_game(dir.path(), "111222333", |player| {
+        player["unlockedPlayerTitle"] = json!([]);
+        player["unlockedPlayerPortrait"] = json!([]);
+    });
+    sign_up(&app, "member", "111222333").await;
+    let (_, body) = sign_in(&app, "member", "hunter2").await;
+
+    let (status, body) = send(&app, Method::POST, "/verify/challenge", body["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn an_ally_code_is_verified_by_one_user() {
+    let (app, dir) = test_app().await;
+    let owner = token(&app).await;
+    sign_up(&app, "other", "987654321").await;
+    let (_, body) = sign_in(&app, "other", "hunter2").await;
+    let other = body["token"].as_str().unwrap().to_string();
+    let (status, _) = send(&app, Method::POST, "/accounts", Some(&other), Some(json!({ "allyCode": ALLY_CODE }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Both open a challenge, the owner finishes first
+    let (status, _) = send_as(&app, Method::POST, "/verify/challenge", Some(&other), Some(ALLY_CODE), None).await;
+    assert_eq!(status, StatusCode::OK);
+    verify(&app, dir.path(), &owner, ALLY_CODE).await;
+
+    let (status, body) = send_as(&app, Method::POST, "/verify", Some(&other), Some(ALLY_CODE), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+    let (status, _) = send_as(&app, Method::POST, "/verify/challenge", Some(&other), Some(ALLY_CODE), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+/// Ally code of fixtures/comlink/player-987654321.json, used as an alt
+const ALT_ALLY_CODE: &str = "987654321";
+
+#[tokio::test]
+async fn linked_ally_codes_are_picked_per_request() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, body) = send(&app, Method::POST, "/accounts", Some(&token), Some(json!({ "allyCode": ALT_ALLY_CODE }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert_eq!(body[1]["name"], "Fixture Officer");
+    assert_eq!(body[1]["isDefault"], false);
+
+    let (_, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(body["name"], "Fixture Player");
+    let (_, body) = send_as(&app, Method::GET, "/account", Some(&token), Some(ALT_ALLY_CODE), None).await;
+    assert_eq!(body["name"], "Fixture Officer");
+
+    let (status, _) = send_as(&app, Method::GET, "/account", Some(&token), Some("111111111"), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send(&app, Method::POST, "/accounts", Some(&token), Some(json!({ "allyCode": ALT_ALLY_CODE }))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn plans_and_verification_are_per_ally_code() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+    send(&app, Method::POST, "/accounts", Some(&token), Some(json!({ "allyCode": ALT_ALLY_CODE }))).await;
+
+    let plan = json!({ "name": "Alt", "icon": "", "characters": [] });
+    let (status, _) = send_as(&app, Method::POST, "/set_plan", Some(&token), Some(ALT_ALLY_CODE), Some(plan.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    verify(&app, dir.path(), &token, ALT_ALLY_CODE).await;
+    let (status, _) = send_as(&app, Method::POST, "/set_plan", Some(&token), Some(ALT_ALLY_CODE), Some(plan)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, body) = send_as(&app, Method::GET, "/get_plan", Some(&token), Some(ALT_ALLY_CODE), None).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&token), None).await;
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn the_default_ally_code_cannot_be_unlinked() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+    send(&app, Method::POST, "/accounts", Some(&token), Some(json!({ "allyCode": ALT_ALLY_CODE }))).await;
+
+    let (status, _) = send(&app, Method::DELETE, &format!("/accounts/{}", ALLY_CODE), Some(&token), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send(&app, Method::PUT, &format!("/accounts/{}/default", ALT_ALLY_CODE), Some(&token), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::DELETE, &format!("/accounts/{}", ALLY_CODE), Some(&token), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // The same token now acts as the new default without signing in again
+    let (_, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(body["name"], "Fixture Officer");
+    let (_, body) = send(&app, Method::GET, "/accounts", Some(&token), None).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn change_password_signs_out_other_sessions() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+    let (_, other) = sign_in(&app, "fixture", "hunter2").await;
+
+    let (status, _) = send(&app, Method::POST, "/password", Some(&token), Some(json!({
+        "currentPassword": "wrong", "newPassword": "hunter3"
+    }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send(&app, Method::POST, "/password", Some(&token), Some(json!({
+        "currentPassword": "hunter2", "newPassword": "hunter3"
+    }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, Method::GET, "/account", other["token"].as_str(), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = sign_in(&app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = sign_in(&app, "fixture", "hunter3").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// The reset token from the last email sent to `to`
+fn reset_token_from_mail(dir: &Path, to: &str) -> String {
+    let mail = std::fs::read_to_string(dir.join("mail").join(format!("{}.txt", to))).unwrap();
+    mail.lines()
+        .rfind(|line| line.len() == 32 && line.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn password_reset_tokens_work_once() {
+    let (app, dir) = test_app().await;
+    token(&app).await;
+
+    let (status, _) = send(&app, Method::POST, "/password/forgot", None, Some(json!({ "username": "fixture" }))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let resetToken = reset_token_from_mail(dir.path(), "fixture@example.com");
+
+    let reset = json!({ "token": resetToken, "newPassword": "hunter3" });
+    let (status, _) = send(&app, Method::POST, "/password/reset", None, Some(reset.clone())).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, body) = send(&app, Method::POST, "/password/reset", None, Some(reset)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    let (status, _) = sign_in(&app, "fixture", "hunter3").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn forgot_password_for_an_unknown_user_looks_the_same() {
+    let (app, dir) = test_app().await;
+
+    let (status, _) = send(&app, Method::POST, "/password/forgot", None, Some(json!({ "username": "nobody" }))).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(!dir.path().join("mail").exists());
+}
+
+#[tokio::test]
+async fn delete_user_removes_their_data() {
+    let (app, dir) = test_app().await;
+    let deleted = verified_token(&app, dir.path()).await;
+    send(&app, Method::POST, "/set_plan", Some(&deleted), Some(json!({ "name": "Jedi", "icon": "", "characters": [] }))).await;
+
+    let (status, _) = send(&app, Method::DELETE, "/user", Some(&deleted), Some(json!({ "password": "wrong" }))).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, Method::DELETE, "/user", Some(&deleted), Some(json!({ "password": "hunter2" }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send(&app, Method::GET, "/account", Some(&deleted), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = sign_in(&app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", dir.path().join("test.sqlite").display())).await.unwrap();
+    for table in ["rosterUnit", "mod", "inventory", "rosterSnapshot"] {
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table} WHERE allyCode = ?")).bind(ALLY_CODE).fetch_one(&pool).await.unwrap();
+        assert_eq!(rows, 0, "{table} still has rows");
+    }
+
+    // Signing up again starts from nothing
+    let fresh = verified_token(&app, dir.path()).await;
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&fresh), None).await;
+    assert!(body.as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn deleting_an_unverified_link_keeps_the_owners_data() {
+    let (app, dir) = test_app().await;
+    let owner = verified_token(&app, dir.path()).await;
+    send(&app, Method::POST, "/set_plan", Some(&owner), Some(json!({ "name": "Jedi", "icon": "", "characters": [] }))).await;
+
+    let other = other_user(&app, "other", ALT_ALLY_CODE).await;
+    send(&app, Method::POST, "/accounts", Some(&other), Some(json!({ "allyCode": ALLY_CODE }))).await;
+    let (status, _) = send(&app, Method::DELETE, "/user", Some(&other), Some(json!({ "password": "hunter2" }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (_, body) = send(&app, Method::GET, "/get_plan", Some(&owner), None).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+/// Ally code of fixtures/comlink/player-111222333.json, a plain member of the fixture guild
+const MEMBER_ALLY_CODE: &str = "111222333";
+
+/// Signs up and signs in another user, returning their token
+async fn other_user(app: &Router, username: &str, allyCode: &str) -> String {
+    let (status, _) = sign_up(app, username, allyCode).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = sign_in(app, username, "hunter2").await;
+    body["token"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn only_officers_can_sync_the_guild() {
+    let (app, dir) = test_app().await;
+    let leader = verified_token(&app, dir.path()).await;
+    let member = other_user(&app, "member", MEMBER_ALLY_CODE).await;
+    verify(&app, dir.path(), &member, MEMBER_ALLY_CODE).await;
+
+    let (status, _) = send(&app, Method::GET, "/guild", Some(&member), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = send(&app, Method::POST, "/guild/refresh", Some(&member), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "forbidden");
+
+    let (status, body) = send(&app, Method::POST, "/guild/refresh", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["members"].as_array().unwrap().len(), 3);
+
+    // Members read what the leader synced
+    let (status, body) = send(&app, Method::GET, "/guild", Some(&member), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "Fixture Guild");
+    assert_eq!(body["members"][0]["memberLevel"], 4);
+    assert_eq!(body["members"].as_array().unwrap().len(), 3);
+
+    // Once the member list is stored a member is turned away without comlink being asked
+    let (status, _) = send(&app, Method::POST, "/guild/refresh", Some(&member), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn guild_units_counts_what_members_own() {
+    let (app, dir) = test_app().await;
+    let leader = verified_token(&app, dir.path()).await;
+
+    // Nothing is known about the guild's members until a refresh
+    let (status, body) = send(&app, Method::GET, "/guild/units", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.as_array().unwrap().iter().all(|unit| unit["owned"] == 0));
+
+    let (status, _) = send(&app, Method::POST, "/guild/refresh", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, Method::GET, "/guild/units", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let baseIds: Vec<&str> = body.as_array().unwrap().iter().map(|unit| unit["baseId"].as_str().unwrap()).collect();
+    assert_eq!(baseIds, ["CAPITALNEGOTIATOR", "GENERALKENOBI", "GRANDMASTERYODA"]);
+
+    // Every member has Kenobi, the leader at 7 stars, G13 and R5 and the others at 6 stars and G11
+    let kenobi = &body[1];
+    assert_eq!(kenobi["owned"], 3);
+    assert_eq!(kenobi["stars"], json!({ "6": 2, "7": 1 }));
+    assert_eq!(kenobi["gear"], json!({ "11": 2, "13": 1 }));
+    assert_eq!(kenobi["relic"], json!({ "5": 1 }));
+    assert_eq!(body[2]["owned"], 1);
+    assert_eq!(body[0]["owned"], 0);
+
+    let (status, body) = send(&app, Method::GET, "/guild/units?category=role_support", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["baseId"], "GRANDMASTERYODA");
+}
+
+#[tokio::test]
+async fn guild_refreshes_wait_and_skip_fresh_members() {
+    let (app, dir) = test_app_with(|config| {
+        config.min_refresh_seconds = 300;
+        config.guild_refresh_seconds = 3600;
+    }).await;
+    let leader = verified_token(&app, dir.path()).await;
+
+    let (status, _) = send(&app, Method::POST, "/guild/refresh", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = send(&app, Method::POST, "/guild/refresh", Some(&leader), None).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(body["error"]["retryAfter"].as_u64().unwrap() > 3500);
+
+    // Linking a member synced moments ago by the guild refresh uses what's stored
+    play_in_game(dir.path(), MEMBER_ALLY_CODE, |player| player["name"] = json!("Renamed Member"));
+    let (status, body) = send(&app, Method::POST, "/accounts", Some(&leader), Some(json!({ "allyCode": MEMBER_ALLY_CODE }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let member = body.as_array().unwrap().iter().find(|account| account["allyCode"] == MEMBER_ALLY_CODE).unwrap();
+    assert_eq!(member["name"], "Fixture Member");
+}
+
+#[tokio::test]
+async fn an_unverified_officer_cannot_sync_the_guild() {
+    let (app, _dir) = test_app().await;
+    let officer = other_user(&app, "officer", ALT_ALLY_CODE).await;
+
+    let (status, _) = send(&app, Method::POST, "/guild/refresh", Some(&officer), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admin_routes_need_an_admin() {
+    let (app, _dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, _) = send(&app, Method::GET, "/admin/users", Some(&token), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // "admin" is listed in the test config's admin_usernames
+    let admin = other_user(&app, "admin", ALT_ALLY_CODE).await;
+    let (status, body) = send(&app, Method::GET, "/admin/users", Some(&admin), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let users = body.as_array().unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[0]["username"], "fixture");
+    assert_eq!(users[0]["isAdmin"], false);
+    assert_eq!(users[1]["isAdmin"], true);
+    let fixtureId = users[0]["id"].as_i64().unwrap();
+
+    let (status, _) = send(&app, Method::PUT, &format!("/admin/users/{}/admin", fixtureId), Some(&admin), Some(json!({ "isAdmin": true }))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::GET, "/admin/users", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, Method::DELETE, &format!("/admin/users/{}", fixtureId), Some(&admin), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn refresh_account_waits_for_the_minimum_interval() {
+    let (app, _dir) = test_app_with(|config| config.min_refresh_seconds = 300).await;
+    let token = token(&app).await;
+
+    // Signing up just pulled the player, so refreshing straight away is too soon
+    let request = Request::builder()
+        .uri("/refreshAccount")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retryAfter: u64 = response.headers()[header::RETRY_AFTER].to_str().unwrap().parse().unwrap();
+    assert!(retryAfter > 0 && retryAfter <= 300);
+
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"]["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn comlink_endpoints_are_rate_limited() {
+    let (app, _dir) = test_app_with(|config| config.rate_limit_per_ip_minute = 3).await;
+
+    for _ in 0..3 {
+        let (status, _) = sign_in(&app, "nobody", "hunter2").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    let (status, body) = sign_in(&app, "nobody", "hunter2").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(body["error"]["retryAfter"].as_u64().unwrap() > 0);
+
+    // Endpoints that don't reach comlink aren't counted
+    let (status, _) = send(&app, Method::POST, "/characters", None, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rate_limits_count_per_user_and_per_ip() {
+    let (app, _dir) = test_app_with(|config| {
+        config.rate_limit_per_minute = 2;
+        config.rate_limit_per_ip_minute = 5;
+    }).await;
+    // Signing up and in count against the IP only
+    let token = token(&app).await;
+
+    for _ in 0..2 {
+        let (status, _) = send(&app, Method::POST, "/verify/challenge", Some(&token), None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = send(&app, Method::POST, "/verify/challenge", Some(&token), None).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // The refused request wasn't counted, so the IP has one left
+    let (status, _) = sign_in(&app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = sign_in(&app, "fixture", "hunter2").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn unreadable_requests_get_json_errors() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    // No body where JSON is expected, a body that isn't JSON, a path id that isn't a number and a bad query value
+    let (status, body) = send(&app, Method::POST, "/characters", None, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/characters")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{\"charId\":"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    let (status, body) = send(&app, Method::DELETE, "/plan/first", Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+
+    let (status, body) = send(&app, Method::GET, "/mods?minSpeed=fast", Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn sign_up_checks_the_username_before_comlink() {
+    let (app, _dir) = test_app().await;
+    sign_up(&app, "fixture", ALLY_CODE).await;
+
+    // 111111111 isn't a fixture player, so reaching comlink would give not_found
+    let (status, _) = sign_up(&app, "fixture", "111111111").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn history_tracks_growth_between_refreshes() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    play_in_game(dir.path(), ALLY_CODE, |player| {
+        let yoda = &mut player["rosterUnit"][1];
+        yoda["currentRarity"] = json!(6);
+        yoda["currentTier"] = json!(13);
+        yoda["relic"]["currentTier"] = json!(3);
+        player["playerRating"]["playerSkillRating"]["skillRating"] = json!(2600);
+    });
+    let (status, _) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, Method::GET, "/history", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let snapshots = body["snapshots"].as_array().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0]["totalStars"], 12);
+    assert_eq!(snapshots[1]["totalStars"], 13);
+    assert_eq!(body["growth"]["totalStars"], 1);
+    assert_eq!(body["growth"]["gear13"], 1);
+    assert_eq!(body["growth"]["relics"], 1);
+    assert_eq!(body["growth"]["skillRating"], 100);
+
+    let (status, body) = send(&app, Method::GET, "/history/GRANDMASTERYODA", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let progress = body["progress"].as_array().unwrap();
+    assert_eq!(progress.len(), 2);
+    assert_eq!(progress[0]["stars"], 5);
+    assert!(progress[0]["relic"].is_null());
+    assert_eq!(progress[1]["stars"], 6);
+    assert_eq!(progress[1]["relic"], 1);
+
+    // Kenobi didn't change so the second refresh adds nothing
+    let (_, body) = send(&app, Method::GET, "/history/GENERALKENOBI", Some(&token), None).await;
+    assert_eq!(body["progress"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn history_filters_by_date() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::GET, "/history?until=2020-01-01", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["snapshots"].as_array().unwrap().is_empty());
+    assert!(body["growth"].is_null());
+
+    let (status, _) = send(&app, Method::GET, "/history/GENERALKENOBI?since=2020-01-01", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, Method::GET, "/history/NOTAUNIT", Some(&token), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(&app, Method::GET, "/history?since=last-month", Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn refresh_account_returns_what_changed() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    // Only the sign up sync so far, everything is new
+    let (status, body) = send(&app, Method::GET, "/history/diff", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["from"].is_null());
+    assert_eq!(body["unlocked"].as_array().unwrap().len(), 2);
+
+    play_in_game(dir.path(), ALLY_CODE, |player| {
+        let yoda = &mut player["rosterUnit"][1];
+        yoda["currentRarity"] = json!(7);
+        yoda["currentTier"] = json!(13);
+        yoda["relic"]["currentTier"] = json!(2);
+        player["rosterUnit"].as_array_mut().unwrap().push(json!({
+            "definitionId": "HERMITYODA:FOUR_STAR",
+            "currentRarity": 4,
+            "currentLevel": 1,
+            "currentTier": 1,
+            "relic": null
+        }));
+    });
+    let (status, body) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["allyCode"], ALLY_CODE);
+    let changes = &body["changes"];
+    assert!(changes["from"].is_string());
+    assert_eq!(changes["unlocked"], json!([{ "baseId": "HERMITYODA", "from": null, "to": 4 }]));
+    assert_eq!(changes["stars"], json!([{ "baseId": "GRANDMASTERYODA", "from": 5, "to": 7 }]));
+    assert_eq!(changes["gear"], json!([{ "baseId": "GRANDMASTERYODA", "from": 8, "to": 13 }]));
+    assert_eq!(changes["relics"], json!([{ "baseId": "GRANDMASTERYODA", "from": null, "to": 0 }]));
+
+    let (status, body) = send(&app, Method::GET, "/history/diff", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(&body, changes);
+
+    let (status, _) = send(&app, Method::GET, "/history/diff?to=2020-01-01", Some(&token), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn unchanged_refreshes_keep_no_snapshot() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let changes = &body["changes"];
+    assert_eq!(changes["from"], changes["to"]);
+    for list in ["unlocked", "stars", "gear", "relics"] {
+        assert!(changes[list].as_array().unwrap().is_empty());
+    }
+
+    let (_, body) = send(&app, Method::GET, "/history", Some(&token), None).await;
+    assert_eq!(body["snapshots"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn old_snapshots_are_pruned() {
+    let (app, dir) = test_app_with(|config| config.snapshot_retention_days = 30).await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let pool = sqlx::SqlitePool::connect(&format!("sqlite://{}", dir.path().join("test.sqlite").display())).await.unwrap();
+    sqlx::query("UPDATE rosterSnapshot SET takenAt = '2020-01-01T00:00:00+00:00'").execute(&pool).await.unwrap();
+
+    // The old snapshot is what this refresh is compared with, so it stays
+    play_in_game(dir.path(), ALLY_CODE, |player| player["rosterUnit"][1]["currentRarity"] = json!(6));
+    let (_, body) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(body["changes"]["stars"], json!([{ "baseId": "GRANDMASTERYODA", "from": 5, "to": 6 }]));
+    let (_, body) = send(&app, Method::GET, "/history?since=2019-01-01", Some(&token), None).await;
+    assert_eq!(body["snapshots"].as_array().unwrap().len(), 2);
+
+    play_in_game(dir.path(), ALLY_CODE, |player| player["rosterUnit"][1]["currentRarity"] = json!(7));
+    send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    let (_, body) = send(&app, Method::GET, "/history?since=2019-01-01", Some(&token), None).await;
+    let snapshots = body["snapshots"].as_array().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0]["totalStars"], 13);
+}
+
+#[tokio::test]
+async fn account_shows_zetas_and_omicrons() {
+    let (app, dir) = test_app().await;
+    let token = token(&app).await;
+
+    let (status, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let skills = &body["rosterUnit"].as_array().unwrap().iter()
+        .find(|unit| unit["definitionId"] == "GENERALKENOBI")
+        .unwrap()["skill"];
+    let skill = |id: &str| skills.as_array().unwrap().iter().find(|skill| skill["id"] == id).unwrap().clone();
+    assert_eq!(skill("basicskill_GENERALKENOBI")["hasZeta"], false);
+    assert_eq!(skill("specialskill_GENERALKENOBI01")["hasZeta"], true);
+    assert_eq!(skill("specialskill_GENERALKENOBI01")["hasOmicron"], false);
+    assert_eq!(skill("leaderskill_GENERALKENOBI")["hasZeta"], false);
+
+    play_in_game(dir.path(), ALLY_CODE, |player| {
+        player["rosterUnit"][0]["skill"][1]["tier"] = json!(7);
+        player["rosterUnit"][0]["purchasedAbilityId"] = json!(["ultimateability_GENERALKENOBI"]);
+    });
+    let (status, body) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["rosterUnit"][0]["skill"][1]["hasOmicron"], true);
+
+    // Purchased abilities are read back from the database too
+    let (_, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    let kenobi = body["rosterUnit"].as_array().unwrap().iter().find(|unit| unit["definitionId"] == "GENERALKENOBI").unwrap();
+    assert_eq!(kenobi["purchasedAbilityId"], json!(["ultimateability_GENERALKENOBI"]));
+}
+
+#[tokio::test]
+async fn gear_needed_skips_equipped_slots() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    play_in_game(dir.path(), ALLY_CODE, |player| {
+        let kenobi = &mut player["rosterUnit"][0];
+        kenobi["currentTier"] = json!(1);
+        kenobi["equipment"] = json!([
+            { "equipmentId": "001", "slot": 0 },
+            { "equipmentId": "002", "slot": 1 }
+        ]);
+    });
+    let (status, _) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, Method::POST, "/gear_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GENERALKENOBI", "goalGear": 2 }]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let gear: Vec<&str> = body["characters"][0]["gear"].as_array().unwrap().iter()
+        .map(|count| count["equipmentId"].as_str().unwrap())
+        .collect();
+    assert_eq!(gear, vec!["003", "004", "005", "006"]);
+}
+
+#[tokio::test]
+async fn gear_needed_breaks_crafted_gear_into_salvage() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    play_in_game(dir.path(), ALLY_CODE, |player| {
+        let kenobi = &mut player["rosterUnit"][0];
+        kenobi["currentTier"] = json!(2);
+        kenobi["equipment"] = json!([]);
+    });
+    let (status, _) = send(&app, Method::GET, "/refreshAccount", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // 007 is crafted from two 001 and 500 credits, the rest of tier 2 has no recipe in the fixture
+    let (status, body) = send(&app, Method::POST, "/gear_needed", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GENERALKENOBI", "goalGear": 3 }]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"].as_array().unwrap().len(), 6);
+    assert_eq!(body["credits"], 500);
+    let salvage = body["salvage"].as_array().unwrap();
+    assert!(salvage.iter().all(|count| count["equipmentId"] != "007"));
+    let mk1 = salvage.iter().find(|count| count["equipmentId"] == "001").unwrap();
+    assert_eq!(mk1["quantity"], 2);
+    assert_eq!(mk1["dropLocations"], json!([{
+        "campaignId": "C01L",
+        "campaignMapId": "A",
+        "campaignNodeDifficulty": 4,
+        "campaignNodeId": "A",
+        "campaignMissionId": "A"
+    }]));
+
+    // Ad-hoc goals are checked against the game data like plan goals
+    for path in ["/gear_needed", "/relics_needed", "/shards_needed"] {
+        let (status, body) = send(&app, Method::POST, path, Some(&token), Some(json!({
+            "characters": [{ "baseId": "NOTAUNIT", "goalGear": 3, "goalRelic": 1, "goalStars": 7 }]
+        }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{path}");
+        assert_eq!(body["error"]["details"][0]["field"], "baseId");
+    }
+}
+
+#[tokio::test]
+async fn mods_are_stored_and_filtered() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::GET, "/mods", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let ids: Vec<&str> = body.as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["mod-kenobi-square", "mod-kenobi-cross", "mod-kenobi-arrow"]);
+    assert_eq!(body[0]["set"], "health");
+    assert_eq!(body[0]["slot"], "square");
+    assert_eq!(body[0]["rarity"], 6);
+    assert_eq!(body[0]["speed"], 16.0);
+    assert_eq!(body[0]["secondaryStats"][0]["rolls"], 1);
+
+    let (_, body) = send(&app, Method::GET, "/mods?set=speed&slot=arrow&primary=speed", Some(&token), None).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["primaryStat"]["value"], 30.0);
+
+    let (_, body) = send(&app, Method::GET, "/mods?minSpeed=8&baseId=GENERALKENOBI", Some(&token), None).await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    let (status, _) = send(&app, Method::GET, "/mods?set=luck", Some(&token), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = send(&app, Method::GET, "/mods/summary", Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["sixDot"], 1);
+    assert_eq!(body["speed15"], 1);
+    assert_eq!(body["bySet"]["speed"], 1);
+
+    // The stored account hands the mods back the way comlink sent them
+    let (_, body) = send(&app, Method::GET, "/account", Some(&token), None).await;
+    let kenobi = body["rosterUnit"].as_array().unwrap().iter().find(|unit| unit["definitionId"] == "GENERALKENOBI").unwrap();
+    assert_eq!(kenobi["equippedStatMod"].as_array().unwrap().len(), 3);
+    assert_eq!(kenobi["equippedStatMod"][0]["primaryStat"]["stat"]["statValueDecimal"], "588");
+}
+
+#[tokio::test]
+async fn optimizer_moves_mods_to_the_first_character() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::POST, "/mods/optimize", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GRANDMASTERYODA", "weights": { "speed": 1 } }]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    // Yoda's 160 base speed plus Kenobi's mods
+    let yoda = &body["characters"][0];
+    assert_eq!(yoda["before"]["speed"], 160.0);
+    assert_eq!(yoda["after"]["speed"], 214.0);
+    assert_eq!(yoda["mods"].as_array().unwrap().len(), 3);
+    let moves = body["moves"].as_array().unwrap();
+    assert_eq!(moves.len(), 3);
+    assert!(moves.iter().all(|m| m["from"] == "GENERALKENOBI" && m["to"] == "GRANDMASTERYODA"));
+
+    // Kenobi goes first this time so nothing moves and Yoda can't reach the minimum
+    let (status, body) = send(&app, Method::POST, "/mods/optimize", Some(&token), Some(json!({
+        "characters": [
+            { "baseId": "GENERALKENOBI", "weights": { "speed": 1 } },
+            { "baseId": "GRANDMASTERYODA", "minimums": { "speed": 200 } }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["characters"][0]["before"]["speed"], 184.0);
+    assert_eq!(body["characters"][0]["after"]["speed"], 184.0);
+    assert!(body["moves"].as_array().unwrap().is_empty());
+    assert_eq!(body["characters"][1]["unmet"], json!(["speed"]));
+
+
+    // Percentages on mods count against the unit's own base stats, so they compare with flat stats
+    let (status, body) = send(&app, Method::POST, "/mods/optimize", Some(&token), Some(json!({
+        "characters": [{ "baseId": "GRANDMASTERYODA", "weights": { "offense": 1, "health": 1 } }]
+    }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let yoda = &body["characters"][0];
+    assert_eq!(yoda["before"]["offense"], 600.0);
+    assert_eq!(yoda["after"]["offense"], 635.28);
+    assert_eq!(yoda["after"]["health"], 3433.6);
+}
+
+#[tokio::test]
+async fn optimizer_rejects_unknown_stats_and_units() {
+    let (app, dir) = test_app().await;
+    let token = verified_token(&app, dir.path()).await;
+
+    let (status, body) = send(&app, Method::POST, "/mods/optimize", Some(&token), Some(json!({
+        "characters": [
+            { "baseId": "GRANDMASTERYODA", "weights": { "luck": 1 } },
+            { "baseId": "HERMITYODA", "weights": { "speed": 1 } }
+        ]
+    }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = body["error"]["details"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["weights.luck", "baseId"]);
+}